@@ -0,0 +1,396 @@
+use crate::register_map::*;
+
+/// Number of writable configuration registers (0x20 to 0x37)
+pub const CONFIG_REGISTER_COUNT: usize = 24;
+
+/// Negative thresholds below this value are flagged by DeviceConfig::lint
+pub const LINT_NTHR_MIN: u8 = 4;
+
+#[derive(Copy, Clone, PartialEq)]
+pub struct DeviceConfig {
+    pub nthr_key: [u8; 7],
+    pub ave_aks_key: [AveAks; 7],
+    pub di_key: [u8; 7],
+    pub fo_mc_guard: FastOutDiMaxCalGuardChannel,
+    pub low_power_mode: LowPowerMode,
+    pub max_on_duration: MaxOnDuration,
+}
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        Self::from(&RegisterMap::default())
+    }
+}
+
+impl From<&RegisterMap> for DeviceConfig {
+    fn from(map: &RegisterMap) -> Self {
+        Self {
+            nthr_key: map.nthr_key.0,
+            ave_aks_key: map.ave_aks_key.0,
+            di_key: map.di_key.0,
+            fo_mc_guard: map.fo_mc_guard,
+            low_power_mode: map.low_power_mode,
+            max_on_duration: map.max_on_duration,
+        }
+    }
+}
+
+impl RegisterMap {
+    pub fn set_config(&mut self, cfg: &DeviceConfig) {
+        self.nthr_key = PerKey(cfg.nthr_key);
+        self.ave_aks_key = PerKey(cfg.ave_aks_key);
+        self.di_key = PerKey(cfg.di_key);
+        self.fo_mc_guard = cfg.fo_mc_guard;
+        self.low_power_mode = cfg.low_power_mode;
+        self.max_on_duration = cfg.max_on_duration;
+    }
+}
+
+impl DeviceConfig {
+    /// All writable configuration registers in ascending address order
+    pub fn registers() -> [Register; CONFIG_REGISTER_COUNT] {
+        let mut regs = [Register::FoMcGuard; CONFIG_REGISTER_COUNT];
+        for key in 0..7 {
+            regs[key] = Register::NthrKey(Key::from(key as u8));
+            regs[7 + key] = Register::AveAksKey(Key::from(key as u8));
+            regs[14 + key] = Register::DIKey(Key::from(key as u8));
+        }
+        regs[21] = Register::FoMcGuard;
+        regs[22] = Register::LowPowerMode;
+        regs[23] = Register::MaxOnDuration;
+
+        regs
+    }
+
+    /// Returns `None` for registers which are not part of the configuration
+    pub fn reg_as_byte(&self, reg: &Register) -> Option<u8> {
+        match reg {
+            Register::NthrKey(key) => Some(self.nthr_key[key.index()]),
+            Register::AveAksKey(key) => Some(self.ave_aks_key[key.index()].as_byte()),
+            Register::DIKey(key) => Some(self.di_key[key.index()]),
+            Register::FoMcGuard => Some(self.fo_mc_guard.as_byte()),
+            Register::LowPowerMode => Some(self.low_power_mode.as_byte()),
+            Register::MaxOnDuration => Some(self.max_on_duration.as_byte()),
+            _ => None,
+        }
+    }
+
+    pub fn check(&self) -> Result<(), ConfigWarning> {
+        let guard_channel = self.fo_mc_guard.guard_channel;
+        if guard_channel < 7 && self.ave_aks_key[guard_channel as usize].aks != 0 {
+            return Err(ConfigWarning::GuardInGroup(Key::from(guard_channel)));
+        }
+
+        Ok(())
+    }
+
+    /// Known bad combinations beyond check, at most 16 warnings are reported
+    #[cfg(feature = "heapless")]
+    pub fn lint(&self) -> heapless::Vec<ConfigWarning, 16> {
+        let mut warnings = heapless::Vec::new();
+        if let Err(warning) = self.check() {
+            let _ = warnings.push(warning);
+        }
+
+        for key in 0..7 {
+            if self.nthr_key[key] < LINT_NTHR_MIN {
+                let _ = warnings.push(ConfigWarning::ThresholdTooLow(Key::from(key as u8)));
+            }
+            if self.di_key[key] == 0 {
+                let _ = warnings.push(ConfigWarning::DetectionIntegratorZero(Key::from(key as u8)));
+            }
+        }
+
+        for group in AksGroup::ALL.iter() {
+            let mut aves = self
+                .ave_aks_key
+                .iter()
+                .filter(|ave_aks| ave_aks.aks == *group as u8)
+                .map(|ave_aks| ave_aks.ave);
+            if let Some(first) = aves.next() {
+                if aves.any(|ave| ave != first) {
+                    let _ = warnings.push(ConfigWarning::AveMismatchInGroup(*group));
+                }
+            }
+        }
+
+        warnings
+    }
+
+    /// Collects every configuration register whose value differs between `self` and `other`
+    pub fn diff(&self, other: &DeviceConfig) -> ConfigMismatch {
+        let mut mismatch = ConfigMismatch::default();
+        for reg in DeviceConfig::registers().iter() {
+            if self.reg_as_byte(reg) != other.reg_as_byte(reg) {
+                let _ = mismatch.push(*reg);
+            }
+        }
+
+        mismatch
+    }
+}
+
+/// Per-key part of the configuration
+#[derive(Copy, Clone, PartialEq)]
+pub struct KeyConfig {
+    pub nthr: u8,
+    pub ave_aks: AveAks,
+    pub di: u8,
+}
+
+impl KeyConfig {
+    pub fn is_valid(&self) -> bool {
+        AveAks::is_valid_ave(self.ave_aks.ave) && AveAks::is_valid_aks(self.ave_aks.aks)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConfigWarning {
+    /// The datasheet advises against using a key of an AKS group as guard channel
+    GuardInGroup(Key),
+    /// An AKS group with fewer than two members suppresses nothing
+    GroupTooSmall(AksGroup),
+    /// A threshold this low reports noise as touches
+    ThresholdTooLow(Key),
+    /// A detection integrator of 0 never confirms a touch
+    DetectionIntegratorZero(Key),
+    /// Keys of an AKS group with different AVE values have incomparable deltas
+    AveMismatchInGroup(AksGroup),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ConfigMismatch {
+    registers: [Register; CONFIG_REGISTER_COUNT],
+    len: usize,
+}
+
+impl Default for ConfigMismatch {
+    fn default() -> Self {
+        Self {
+            registers: [Register::FoMcGuard; CONFIG_REGISTER_COUNT],
+            len: 0,
+        }
+    }
+}
+
+impl ConfigMismatch {
+    pub fn registers(&self) -> &[Register] {
+        &self.registers[..self.len]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Hands `reg` back when the list is full, like `heapless::Vec::push`
+    fn push(&mut self, reg: Register) -> Result<(), Register> {
+        let slot = self.registers.get_mut(self.len).ok_or(reg)?;
+        *slot = reg;
+        self.len += 1;
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ProfileError {
+    Malformed,
+    UnknownField,
+    InvalidValue,
+}
+
+#[cfg(feature = "std")]
+impl DeviceConfig {
+    /// Parses a comma separated profile like "nthr=20,ave=8,di=4" on top of the defaults.
+    /// The per-key fields nthr, ave, aks and di apply to every key, or to a single key
+    /// when suffixed with its index (e.g. "nthr3=25"). Global fields are fast_out,
+    /// max_cal, guard, lpm and max_on, all taking raw register values.
+    pub fn parse(profile: &str) -> Result<DeviceConfig, ProfileError> {
+        let mut cfg = DeviceConfig::default();
+        for entry in profile.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let mut parts = entry.splitn(2, '=');
+            let field = parts.next().unwrap_or_default().trim();
+            let value = parts.next().ok_or(ProfileError::Malformed)?.trim();
+            let value = value.parse().map_err(|_| ProfileError::InvalidValue)?;
+            cfg.set_field(field, value)?;
+        }
+
+        Ok(cfg)
+    }
+
+    fn set_field(&mut self, field: &str, value: u8) -> Result<(), ProfileError> {
+        match field {
+            "fast_out" => self.fo_mc_guard.fast_out = parse_flag(value)?,
+            "max_cal" => self.fo_mc_guard.max_cal = parse_flag(value)?,
+            "guard" if value <= 0x07 => self.fo_mc_guard.guard_channel = value,
+            "guard" => return Err(ProfileError::InvalidValue),
+            "lpm" => self.low_power_mode.update(value),
+            "max_on" => self.max_on_duration.update(value),
+            _ => return self.set_key_field(field, value),
+        }
+
+        Ok(())
+    }
+
+    fn set_key_field(&mut self, field: &str, value: u8) -> Result<(), ProfileError> {
+        let digit = field.as_bytes().last().copied().unwrap_or_default();
+        let (name, keys) = match Key::from_ascii_digit(digit) {
+            Some(key) => (&field[..field.len() - 1], key.index()..key.index() + 1),
+            None => (field, 0..7),
+        };
+
+        for key in keys {
+            match name {
+                "nthr" => self.nthr_key[key] = value,
+                "ave" if AveAks::is_valid_ave(value) => self.ave_aks_key[key].ave = value,
+                "aks" if AveAks::is_valid_aks(value) => self.ave_aks_key[key].aks = value,
+                "ave" | "aks" => return Err(ProfileError::InvalidValue),
+                "di" => self.di_key[key] = value,
+                _ => return Err(ProfileError::UnknownField),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+fn parse_flag(value: u8) -> Result<bool, ProfileError> {
+    match value {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(ProfileError::InvalidValue),
+    }
+}
+
+#[cfg(all(test, any(feature = "std", feature = "heapless")))]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn parse_applies_global_and_per_key_fields() {
+        let cfg =
+            DeviceConfig::parse("nthr=20, ave=8,aks=0, nthr3=25,di=2,fast_out=1,lpm=4").unwrap();
+
+        assert_eq!(cfg.nthr_key, [20, 20, 20, 25, 20, 20, 20]);
+        assert!(cfg
+            .ave_aks_key
+            .iter()
+            .all(|ave_aks| ave_aks.ave == 8 && ave_aks.aks == 0));
+        assert_eq!(cfg.di_key, [2; 7]);
+        assert!(cfg.fo_mc_guard.fast_out);
+        assert_eq!(cfg.low_power_mode.as_byte(), 4);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn parse_keeps_defaults_for_missing_fields() {
+        let cfg = DeviceConfig::parse("di5=7").unwrap();
+
+        let mut expected = DeviceConfig::default();
+        expected.di_key[5] = 7;
+        assert!(cfg == expected);
+        assert!(DeviceConfig::parse("").unwrap() == DeviceConfig::default());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn parse_rejects_malformed_profiles() {
+        assert_eq!(
+            DeviceConfig::parse("nthr").err(),
+            Some(ProfileError::Malformed)
+        );
+        assert_eq!(
+            DeviceConfig::parse("nthr=x").err(),
+            Some(ProfileError::InvalidValue)
+        );
+        assert_eq!(
+            DeviceConfig::parse("nthr=256").err(),
+            Some(ProfileError::InvalidValue)
+        );
+        assert_eq!(
+            DeviceConfig::parse("ave=3").err(),
+            Some(ProfileError::InvalidValue)
+        );
+        assert_eq!(
+            DeviceConfig::parse("fast_out=2").err(),
+            Some(ProfileError::InvalidValue)
+        );
+        assert_eq!(
+            DeviceConfig::parse("nthr7=1").err(),
+            Some(ProfileError::UnknownField)
+        );
+        assert_eq!(
+            DeviceConfig::parse("foo=1").err(),
+            Some(ProfileError::UnknownField)
+        );
+    }
+
+    #[cfg(feature = "heapless")]
+    #[test]
+    fn lint_passes_the_defaults() {
+        assert!(DeviceConfig::default().lint().is_empty());
+    }
+
+    #[cfg(feature = "heapless")]
+    #[test]
+    fn lint_reports_each_warning() {
+        let mut cfg = DeviceConfig::default();
+        cfg.nthr_key[2] = LINT_NTHR_MIN - 1;
+        assert_eq!(cfg.lint(), [ConfigWarning::ThresholdTooLow(Key::Key2)]);
+
+        let mut cfg = DeviceConfig::default();
+        cfg.di_key[5] = 0;
+        assert_eq!(
+            cfg.lint(),
+            [ConfigWarning::DetectionIntegratorZero(Key::Key5)]
+        );
+
+        let mut cfg = DeviceConfig::default();
+        cfg.ave_aks_key[3].ave = 16;
+        assert_eq!(
+            cfg.lint(),
+            [ConfigWarning::AveMismatchInGroup(AksGroup::Group1)]
+        );
+
+        let mut cfg = DeviceConfig::default();
+        cfg.fo_mc_guard.guard_channel = 1;
+        assert_eq!(cfg.lint(), [ConfigWarning::GuardInGroup(Key::Key1)]);
+    }
+
+    #[cfg(feature = "heapless")]
+    #[test]
+    fn lint_reports_all_warnings_in_order() {
+        let mut cfg = DeviceConfig::default();
+        cfg.fo_mc_guard.guard_channel = 6;
+        cfg.nthr_key = [0; 7];
+        cfg.di_key = [0; 7];
+        cfg.ave_aks_key[1].ave = 4;
+
+        let warnings = cfg.lint();
+        assert_eq!(warnings.len(), 16);
+        assert_eq!(warnings[0], ConfigWarning::GuardInGroup(Key::Key6));
+        assert_eq!(warnings[1], ConfigWarning::ThresholdTooLow(Key::Key0));
+        assert_eq!(
+            warnings[2],
+            ConfigWarning::DetectionIntegratorZero(Key::Key0)
+        );
+        assert_eq!(
+            warnings[15],
+            ConfigWarning::AveMismatchInGroup(AksGroup::Group1)
+        );
+    }
+
+    #[test]
+    fn config_mismatch_push_stops_at_capacity() {
+        let mut mismatch = ConfigMismatch::default();
+        for _ in 0..CONFIG_REGISTER_COUNT {
+            assert_eq!(mismatch.push(Register::LowPowerMode), Ok(()));
+        }
+
+        assert_eq!(mismatch.push(Register::Reset), Err(Register::Reset));
+        assert_eq!(mismatch.registers().len(), CONFIG_REGISTER_COUNT);
+    }
+}
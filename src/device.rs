@@ -0,0 +1,84 @@
+use crate::error::Error;
+use crate::register_map::{Key, Register};
+use crate::At42qt1070;
+use embedded_hal::blocking::i2c;
+
+/// Operations common to AT42QT touch devices, to write application code generic over the part
+pub trait TouchDevice {
+    type BusError;
+
+    fn sync_all(&mut self) -> Result<(), Self::BusError>;
+
+    fn sync_one(&mut self, reg: &Register) -> Result<(), Self::BusError>;
+
+    fn device_reg(&self, reg: &Register) -> u8;
+
+    fn write_register(&mut self, reg: &Register, value: u8) -> Result<(), Error<Self::BusError>>;
+
+    fn read_key_status(&mut self, key: Key) -> Result<bool, Self::BusError>;
+
+    fn read_key_signal(&mut self, key: Key) -> Result<u16, Self::BusError>;
+}
+
+impl<I2C, E> TouchDevice for At42qt1070<I2C>
+where
+    I2C: i2c::Write<Error = E> + i2c::WriteRead<Error = E>,
+{
+    type BusError = E;
+
+    fn sync_all(&mut self) -> Result<(), E> {
+        At42qt1070::sync_all(self)
+    }
+
+    fn sync_one(&mut self, reg: &Register) -> Result<(), E> {
+        At42qt1070::sync_one(self, reg)
+    }
+
+    fn device_reg(&self, reg: &Register) -> u8 {
+        At42qt1070::device_reg(self, reg)
+    }
+
+    fn write_register(&mut self, reg: &Register, value: u8) -> Result<(), Error<E>> {
+        At42qt1070::write_register(self, reg, value)
+    }
+
+    fn read_key_status(&mut self, key: Key) -> Result<bool, E> {
+        At42qt1070::read_key_status(self, key)
+    }
+
+    fn read_key_signal(&mut self, key: Key) -> Result<u16, E> {
+        At42qt1070::read_key_signal(self, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockI2c;
+
+    // Application code only knowing the trait
+    fn touched_keys<D: TouchDevice>(device: &mut D) -> Result<u8, D::BusError> {
+        let mut touched = 0;
+        for key in 0..7 {
+            if device.read_key_status(Key::from(key))? {
+                touched += 1;
+            }
+        }
+
+        Ok(touched)
+    }
+
+    #[test]
+    fn at42qt1070_is_a_touch_device() {
+        let mut i2c = MockI2c::new();
+        i2c.regs[0x03] = 0b010_0101;
+        let mut sensor = At42qt1070::new(i2c);
+
+        assert_eq!(touched_keys(&mut sensor), Ok(3));
+        TouchDevice::write_register(&mut sensor, &Register::NthrKey(Key::Key1), 30).unwrap();
+        assert_eq!(
+            TouchDevice::device_reg(&sensor, &Register::NthrKey(Key::Key1)),
+            30
+        );
+    }
+}
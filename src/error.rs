@@ -0,0 +1,30 @@
+use crate::config::{ConfigMismatch, ConfigWarning};
+use crate::register_map::Register;
+
+#[derive(Debug)]
+pub enum Error<E> {
+    I2c(E),
+    ConfigMismatch(ConfigMismatch),
+    InvalidConfig(ConfigWarning),
+    InvalidArgument,
+    NoDevice,
+    IdentityMismatch {
+        expected: u8,
+        actual: u8,
+    },
+    ReadOnly(Register),
+    ChangeLineStuck,
+    /// The given address looks like the 8 bit form, the 7 bit address is 0x1B
+    EightBitAddress(u8),
+    VerifyMismatch {
+        register: Register,
+        expected: u8,
+        actual: u8,
+    },
+}
+
+#[derive(Debug)]
+pub enum SignalError<E> {
+    I2c(E),
+    Overflow,
+}
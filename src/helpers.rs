@@ -0,0 +1,753 @@
+use crate::register_map::*;
+
+/// Software model of the adjacent key suppression (AKS) of the device.
+/// Within every group only the touched key with the largest delta reports,
+/// ties go to the lower key. This approximates, but does not replicate, the
+/// silicon behavior, which also depends on the order in which keys were touched.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AksGrouping {
+    pub groups: [AksGroup; 7],
+}
+
+impl AksGrouping {
+    pub fn new(groups: [AksGroup; 7]) -> Self {
+        Self { groups }
+    }
+
+    pub fn from_register_map(map: &RegisterMap) -> Self {
+        let mut groups = [AksGroup::None; 7];
+        for (group, ave_aks) in groups.iter_mut().zip(map.ave_aks_key.0.iter()) {
+            *group = AksGroup::from(ave_aks.aks);
+        }
+
+        Self { groups }
+    }
+
+    /// Keys of `group`, with AksGroup::None the keys which are not part of any group
+    pub fn members(&self, group: AksGroup) -> KeySet {
+        let mut members = KeySet::default();
+        for key in 0..7 {
+            if self.groups[key] == group {
+                members.insert(Key::from(key as u8));
+            }
+        }
+
+        members
+    }
+
+    pub fn apply(&self, touched: KeyMask, deltas: &[u16; 7]) -> KeyMask {
+        let mut reported = KeyMask::default();
+        for key in 0..7 {
+            if !touched.contains(Key::from(key as u8)) {
+                continue;
+            }
+
+            let group = self.groups[key];
+            let suppressed = group != AksGroup::None
+                && (0..7).any(|other| {
+                    other != key
+                        && self.groups[other] == group
+                        && touched.contains(Key::from(other as u8))
+                        && (deltas[other] > deltas[key]
+                            || (deltas[other] == deltas[key] && other < key))
+                });
+            if !suppressed {
+                reported.insert(Key::from(key as u8));
+            }
+        }
+
+        reported
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Edge {
+    Pressed,
+    Released,
+}
+
+/// Yields the edges between two key masks, in ascending key order
+#[derive(Copy, Clone, Debug)]
+pub struct KeyEvents {
+    previous: KeyMask,
+    current: KeyMask,
+    next_key: u8,
+}
+
+impl KeyEvents {
+    pub fn new(previous: KeyMask, current: KeyMask) -> Self {
+        Self {
+            previous,
+            current,
+            next_key: 0,
+        }
+    }
+}
+
+impl Iterator for KeyEvents {
+    type Item = KeyEvent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next_key < 7 {
+            let key = Key::from(self.next_key);
+            self.next_key += 1;
+
+            match (self.previous.contains(key), self.current.contains(key)) {
+                (false, true) => return Some((key, Edge::Pressed)),
+                (true, false) => return Some((key, Edge::Released)),
+                _ => {}
+            }
+        }
+
+        None
+    }
+}
+
+pub type KeyEvent = (Key, Edge);
+
+/// Newly pressed keys split by which of two successive status reads first reported them
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PressOrder {
+    pub earlier: KeyMask,
+    pub later: KeyMask,
+}
+
+impl PressOrder {
+    pub fn new(previous: KeyMask, first: KeyMask, second: KeyMask) -> Self {
+        let earlier = first & second & !previous;
+        Self {
+            earlier,
+            later: second & !previous & !earlier,
+        }
+    }
+}
+
+/// Centroid of the deltas of seven pads in a row, scaled to 0..=1000 (Key0 to Key6).
+/// None when no delta exceeds the threshold of its key.
+pub fn slider_position(deltas: &[u16; 7], thresholds: &[u8; 7]) -> Option<u16> {
+    if !deltas
+        .iter()
+        .zip(thresholds.iter())
+        .any(|(delta, nthr)| *delta > *nthr as u16)
+    {
+        return None;
+    }
+
+    let mut weighted = 0u32;
+    let mut total = 0u32;
+    for (key, delta) in deltas.iter().enumerate() {
+        weighted += *delta as u32 * key as u32;
+        total += *delta as u32;
+    }
+
+    Some((weighted * 1000 / (total * 6)) as u16)
+}
+
+/// Counts how often in a row the CHANGE line was asserted again right after clearing it.
+/// Reaching the limit means the line is stuck and the application should fall back to polling.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ChangeLineMonitor {
+    pub limit: u8,
+    count: u8,
+}
+
+impl ChangeLineMonitor {
+    pub fn new(limit: u8) -> Self {
+        Self { limit, count: 0 }
+    }
+
+    /// Returns true once the line was still asserted `limit` consecutive times, a limit of 0
+    /// behaves like 1 since a line that was never seen asserted can not be stuck
+    pub fn update(&mut self, still_asserted: bool) -> bool {
+        if still_asserted {
+            self.count = self.count.saturating_add(1);
+        } else {
+            self.count = 0;
+        }
+
+        self.is_stuck()
+    }
+
+    pub fn is_stuck(&self) -> bool {
+        self.count >= self.limit.max(1)
+    }
+
+    pub fn reset(&mut self) {
+        self.count = 0;
+    }
+}
+
+/// Tracks acquisition overflows over windows of `window` status reads. When `limit` or more
+/// reads of the last complete window overflowed, halving the AVE of the key with the highest
+/// AVE is suggested, as a shorter acquisition is the usual fix.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OverflowAdvisor {
+    pub window: u16,
+    pub limit: u16,
+    ave: [u8; 7],
+    reads: u16,
+    overflows: u16,
+    last_window_overflows: u16,
+}
+
+impl OverflowAdvisor {
+    pub fn new(window: u16, limit: u16, map: &RegisterMap) -> Self {
+        let mut advisor = Self {
+            window,
+            limit,
+            ave: [0; 7],
+            reads: 0,
+            overflows: 0,
+            last_window_overflows: 0,
+        };
+        advisor.update_ave(map);
+
+        advisor
+    }
+
+    /// Call after changing the AVE values, e.g. when following a suggestion
+    pub fn update_ave(&mut self, map: &RegisterMap) {
+        for (ave, ave_aks) in self.ave.iter_mut().zip(map.ave_aks_key.0.iter()) {
+            *ave = ave_aks.ave;
+        }
+    }
+
+    pub fn record(&mut self, status: &DetectionStatus) {
+        self.reads += 1;
+        if status.overflow {
+            self.overflows += 1;
+        }
+
+        if self.reads >= self.window {
+            self.last_window_overflows = self.overflows;
+            self.reads = 0;
+            self.overflows = 0;
+        }
+    }
+
+    /// Key and the halved AVE to write to it, None without frequent overflows or when every
+    /// AVE is already 1
+    pub fn suggest_overflow_fix(&self) -> Option<(Key, u8)> {
+        if self.last_window_overflows < self.limit.max(1) {
+            return None;
+        }
+
+        let (key, ave) = self
+            .ave
+            .iter()
+            .enumerate()
+            .max_by_key(|(key, ave)| (**ave, core::cmp::Reverse(*key)))?;
+        if *ave <= 1 {
+            return None;
+        }
+
+        Some((Key::from(key as u8), ave / 2))
+    }
+}
+
+/// Change of every signal per millisecond between successive frames. The first frame
+/// yields zeros, an elapsed time of 0 is treated as 1 ms.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SignalRate {
+    previous: Option<[u16; 7]>,
+}
+
+impl SignalRate {
+    pub fn new() -> Self {
+        Self { previous: None }
+    }
+
+    pub fn update(&mut self, signals: [u16; 7], elapsed_ms: u32) -> [i32; 7] {
+        let mut rates = [0i32; 7];
+        if let Some(previous) = self.previous {
+            let elapsed_ms = elapsed_ms.clamp(1, i32::MAX as u32) as i32;
+            for (rate, (signal, previous)) in
+                rates.iter_mut().zip(signals.iter().zip(previous.iter()))
+            {
+                *rate = (*signal as i32 - *previous as i32) / elapsed_ms;
+            }
+        }
+        self.previous = Some(signals);
+
+        rates
+    }
+
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+}
+
+/// Maps physical pad positions (e.g. along a slider) to the keys they are wired to
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct KeyMap<const N: usize> {
+    pads: [Key; N],
+}
+
+impl<const N: usize> KeyMap<N> {
+    /// None when a key is used for more than one pad
+    pub fn new(pads: [Key; N]) -> Option<Self> {
+        let mut used = KeyMask::default();
+        for key in pads.iter() {
+            if used.contains(*key) {
+                return None;
+            }
+            used.insert(*key);
+        }
+
+        Some(Self { pads })
+    }
+
+    pub fn physical_to_key(&self, position: usize) -> Option<Key> {
+        self.pads.get(position).copied()
+    }
+
+    pub fn key_to_physical(&self, key: Key) -> Option<usize> {
+        self.pads.iter().position(|pad| *pad == key)
+    }
+}
+
+/// Long running statistics of a signal. The sum is kept in a u64, which can not overflow
+/// before 2^48 samples, so the mean stays exact for any realistic measurement duration.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct NoiseStats {
+    band: NoiseBand,
+    sum: u64,
+    count: u64,
+}
+
+impl NoiseStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, sample: u16) {
+        self.band.add(sample);
+        self.sum = self.sum.saturating_add(sample as u64);
+        self.count = self.count.saturating_add(1);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// None until the first sample
+    pub fn min(&self) -> Option<u16> {
+        self.mean().map(|_| self.band.min)
+    }
+
+    pub fn max(&self) -> Option<u16> {
+        self.mean().map(|_| self.band.max)
+    }
+
+    pub fn mean(&self) -> Option<u16> {
+        if self.count == 0 {
+            return None;
+        }
+
+        Some((self.sum / self.count) as u16)
+    }
+
+    pub fn band(&self) -> NoiseBand {
+        self.band
+    }
+}
+
+/// Software hysteresis on successive key masks. A key enters the pressed state after
+/// on_count consecutive touched samples and leaves it after off_count untouched ones.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Hysteresis {
+    pub on_count: u8,
+    pub off_count: u8,
+    state: KeyMask,
+    counters: [u8; 7],
+}
+
+impl Hysteresis {
+    pub fn new(on_count: u8, off_count: u8) -> Self {
+        Self {
+            on_count,
+            off_count,
+            state: KeyMask::default(),
+            counters: [0; 7],
+        }
+    }
+
+    pub fn update(&mut self, raw: KeyMask) -> KeyMask {
+        for key in 0..7 {
+            let key_id = Key::from(key as u8);
+            let pressed = self.state.contains(key_id);
+            if raw.contains(key_id) == pressed {
+                self.counters[key] = 0;
+                continue;
+            }
+
+            self.counters[key] = self.counters[key].saturating_add(1);
+            let required = if pressed {
+                self.off_count
+            } else {
+                self.on_count
+            };
+            if self.counters[key] >= required {
+                self.counters[key] = 0;
+                if pressed {
+                    self.state.remove(key_id);
+                } else {
+                    self.state.insert(key_id);
+                }
+            }
+        }
+
+        self.state
+    }
+
+    pub fn state(&self) -> KeyMask {
+        self.state
+    }
+}
+
+/// Smallest and largest signal seen while the key is untouched
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NoiseBand {
+    pub min: u16,
+    pub max: u16,
+}
+
+impl Default for NoiseBand {
+    fn default() -> Self {
+        Self {
+            min: u16::MAX,
+            max: 0,
+        }
+    }
+}
+
+impl NoiseBand {
+    pub fn add(&mut self, signal: u16) {
+        self.min = self.min.min(signal);
+        self.max = self.max.max(signal);
+    }
+
+    pub fn peak_to_peak(&self) -> u16 {
+        self.max.saturating_sub(self.min)
+    }
+
+    /// Suggested negative threshold, clamped to 1..=255 as 0 would report every key as touched
+    pub fn recommend_threshold(&self, margin: u8) -> u8 {
+        let nthr = self.peak_to_peak().saturating_add(margin as u16);
+        nthr.clamp(1, u8::MAX as u16) as u8
+    }
+}
+
+/// Allocation free queue to hand key events from an interrupt handler to the main loop.
+/// It holds at most N - 1 events. `split` returns the producer half for the interrupt handler
+/// and the consumer half for the main loop, which can be used from both contexts without a
+/// critical section. When the queue is full new events are dropped, `push_key_events`
+/// returns how many, so drops are counted on the producer side.
+#[cfg(feature = "heapless")]
+pub struct EventSink<const N: usize> {
+    queue: heapless::spsc::Queue<KeyEvent, N>,
+}
+
+#[cfg(feature = "heapless")]
+pub type EventProducer<'a, const N: usize> = heapless::spsc::Producer<'a, KeyEvent, N>;
+
+#[cfg(feature = "heapless")]
+pub type EventConsumer<'a, const N: usize> = heapless::spsc::Consumer<'a, KeyEvent, N>;
+
+#[cfg(feature = "heapless")]
+impl<const N: usize> EventSink<N> {
+    pub const fn new() -> Self {
+        Self {
+            queue: heapless::spsc::Queue::new(),
+        }
+    }
+
+    pub fn split(&mut self) -> (EventProducer<'_, N>, EventConsumer<'_, N>) {
+        self.queue.split()
+    }
+}
+
+#[cfg(feature = "heapless")]
+impl<const N: usize> Default for EventSink<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_events_yield_edges_in_key_order() {
+        let previous = KeyMask(0b000_0101);
+        let current = KeyMask(0b100_0110);
+
+        let mut events = KeyEvents::new(previous, current);
+        assert_eq!(events.next(), Some((Key::Key0, Edge::Released)));
+        assert_eq!(events.next(), Some((Key::Key1, Edge::Pressed)));
+        assert_eq!(events.next(), Some((Key::Key6, Edge::Pressed)));
+        assert_eq!(events.next(), None);
+    }
+
+    #[test]
+    fn key_events_are_empty_without_change() {
+        assert_eq!(KeyEvents::new(KeyMask(0x7F), KeyMask(0x7F)).count(), 0);
+        assert_eq!(KeyEvents::new(KeyMask(0), KeyMask(0)).count(), 0);
+    }
+
+    const GROUPS: [AksGroup; 7] = [
+        AksGroup::None,
+        AksGroup::Group1,
+        AksGroup::Group1,
+        AksGroup::Group1,
+        AksGroup::Group2,
+        AksGroup::Group2,
+        AksGroup::None,
+    ];
+
+    #[test]
+    fn aks_grouping_reports_the_strongest_key_per_group() {
+        let grouping = AksGrouping::new(GROUPS);
+        let deltas = [50, 10, 30, 20, 40, 40, 5];
+
+        // Key1 to Key3 and Key4/Key5 overlap within their groups, ties go to the lower key
+        let reported = grouping.apply(KeyMask(0x7F), &deltas);
+        assert_eq!(
+            reported,
+            KeyMask(0b100_0000 | 0b001_0000 | 0b000_0100 | 0b000_0001)
+        );
+
+        // Without a stronger neighbour a weak key reports
+        let reported = grouping.apply(KeyMask(0b000_1010), &deltas);
+        assert_eq!(reported, KeyMask(0b000_1000));
+    }
+
+    #[test]
+    fn aks_grouping_with_empty_inputs() {
+        let deltas = [0; 7];
+        assert!(AksGrouping::new(GROUPS)
+            .apply(KeyMask(0), &deltas)
+            .is_empty());
+
+        // Without any group every touched key reports
+        let ungrouped = AksGrouping::default();
+        assert_eq!(ungrouped.apply(KeyMask(0x7F), &deltas), KeyMask(0x7F));
+        assert!(ungrouped.members(AksGroup::Group3).is_empty());
+        assert_eq!(ungrouped.members(AksGroup::None), KeyMask(0x7F));
+    }
+
+    #[test]
+    fn aks_grouping_members() {
+        let grouping = AksGrouping::new(GROUPS);
+        assert_eq!(grouping.members(AksGroup::Group1), KeyMask(0b000_1110));
+        assert_eq!(grouping.members(AksGroup::None), KeyMask(0b100_0001));
+
+        let grouping = AksGrouping::from_register_map(&RegisterMap::default());
+        assert_eq!(grouping.members(AksGroup::Group1), KeyMask(0b111_1110));
+    }
+
+    #[test]
+    fn noise_band_threshold_is_clamped() {
+        let mut band = NoiseBand::default();
+        band.add(100);
+        assert_eq!(band.peak_to_peak(), 0);
+        assert_eq!(band.recommend_threshold(0), 1);
+
+        band.add(110);
+        assert_eq!(band.recommend_threshold(4), 14);
+
+        band.add(400);
+        assert_eq!(band.recommend_threshold(10), 255);
+    }
+
+    #[test]
+    fn hysteresis_uses_asymmetric_counts() {
+        let mut hysteresis = Hysteresis::new(2, 3);
+        let key = KeyMask::from(Key::Key4);
+
+        assert!(hysteresis.update(key).is_empty());
+        assert_eq!(hysteresis.update(key), key);
+
+        assert_eq!(hysteresis.update(KeyMask(0)), key);
+        assert_eq!(hysteresis.update(KeyMask(0)), key);
+        assert!(hysteresis.update(KeyMask(0)).is_empty());
+    }
+
+    #[test]
+    fn hysteresis_restarts_counting_after_a_bounce() {
+        let mut hysteresis = Hysteresis::new(2, 1);
+        let key = KeyMask::from(Key::Key0);
+
+        assert!(hysteresis.update(key).is_empty());
+        assert!(hysteresis.update(KeyMask(0)).is_empty());
+        assert!(hysteresis.update(key).is_empty());
+        assert_eq!(hysteresis.update(key), key);
+        assert!(hysteresis.update(KeyMask(0)).is_empty());
+        assert!(hysteresis.state().is_empty());
+    }
+
+    #[test]
+    fn key_map_translates_a_permutation() {
+        let map = KeyMap::new([Key::Key3, Key::Key0, Key::Key6]).unwrap();
+        assert_eq!(map.physical_to_key(0), Some(Key::Key3));
+        assert_eq!(map.physical_to_key(2), Some(Key::Key6));
+        assert_eq!(map.physical_to_key(3), None);
+        assert_eq!(map.key_to_physical(Key::Key0), Some(1));
+        assert_eq!(map.key_to_physical(Key::Key1), None);
+    }
+
+    #[test]
+    fn key_map_rejects_duplicate_keys() {
+        assert!(KeyMap::new([Key::Key1, Key::Key2, Key::Key1]).is_none());
+        assert!(KeyMap::<0>::new([]).is_some());
+    }
+
+    #[test]
+    fn slider_position_covers_both_ends() {
+        let thresholds = [20; 7];
+
+        assert_eq!(
+            slider_position(&[80, 0, 0, 0, 0, 0, 0], &thresholds),
+            Some(0)
+        );
+        assert_eq!(
+            slider_position(&[0, 0, 0, 0, 0, 0, 80], &thresholds),
+            Some(1000)
+        );
+        assert_eq!(
+            slider_position(&[0, 0, 60, 60, 0, 0, 0], &thresholds),
+            Some(416)
+        );
+        assert_eq!(
+            slider_position(&[0, 0, 0, 90, 30, 0, 0], &thresholds),
+            Some(541)
+        );
+    }
+
+    #[test]
+    fn slider_position_needs_a_delta_above_its_threshold() {
+        assert_eq!(slider_position(&[0; 7], &[20; 7]), None);
+        assert_eq!(slider_position(&[15, 20, 0, 0, 0, 0, 0], &[20; 7]), None);
+        assert_eq!(
+            slider_position(&[15, 20, 0, 0, 0, 0, 0], &[10, 30, 30, 30, 30, 30, 30]),
+            Some(95)
+        );
+    }
+
+    #[test]
+    fn change_line_monitor_counts_consecutive_assertions() {
+        let mut monitor = ChangeLineMonitor::new(3);
+        assert!(!monitor.update(true));
+        assert!(!monitor.update(true));
+        assert!(!monitor.update(false));
+        assert!(!monitor.update(true));
+        assert!(!monitor.update(true));
+        assert!(monitor.update(true));
+        assert!(monitor.is_stuck());
+
+        monitor.reset();
+        assert!(!monitor.is_stuck());
+    }
+
+    #[test]
+    fn change_line_monitor_with_limit_0_behaves_like_1() {
+        let mut monitor = ChangeLineMonitor::new(0);
+        assert!(!monitor.is_stuck());
+        assert!(!monitor.update(false));
+        assert!(monitor.update(true));
+    }
+
+    fn record_window(advisor: &mut OverflowAdvisor, overflows: &[bool]) {
+        for overflow in overflows.iter() {
+            advisor.record(&DetectionStatus {
+                calibrate: false,
+                overflow: *overflow,
+                touch: false,
+            });
+        }
+    }
+
+    #[test]
+    fn overflow_advisor_suggests_halving_the_highest_ave() {
+        let mut map = RegisterMap::default();
+        map.ave_aks_key.0[4].ave = 16;
+        let mut advisor = OverflowAdvisor::new(4, 2, &map);
+
+        record_window(&mut advisor, &[false, true, false, false]);
+        assert_eq!(advisor.suggest_overflow_fix(), None);
+
+        record_window(&mut advisor, &[true, false, true, true]);
+        assert_eq!(advisor.suggest_overflow_fix(), Some((Key::Key4, 8)));
+        // An incomplete window keeps the result of the last complete one
+        record_window(&mut advisor, &[false, false]);
+        assert_eq!(advisor.suggest_overflow_fix(), Some((Key::Key4, 8)));
+
+        map.ave_aks_key.0[4].ave = 8;
+        advisor.update_ave(&map);
+        assert_eq!(advisor.suggest_overflow_fix(), Some((Key::Key1, 4)));
+
+        record_window(&mut advisor, &[false, false]);
+        assert_eq!(advisor.suggest_overflow_fix(), None);
+    }
+
+    #[test]
+    fn overflow_advisor_has_nothing_to_suggest_at_ave_1() {
+        let mut map = RegisterMap::default();
+        for ave_aks in map.ave_aks_key.0.iter_mut() {
+            ave_aks.ave = 1;
+        }
+        let mut advisor = OverflowAdvisor::new(2, 1, &map);
+
+        record_window(&mut advisor, &[true, true]);
+        assert_eq!(advisor.suggest_overflow_fix(), None);
+    }
+
+    #[test]
+    fn signal_rate_per_millisecond() {
+        let mut rate = SignalRate::new();
+        assert_eq!(rate.update([500; 7], 10), [0; 7]);
+
+        let rates = rate.update([520, 500, 480, 500, 500, 500, 509], 10);
+        assert_eq!(rates, [2, 0, -2, 0, 0, 0, 0]);
+
+        // An elapsed time of 0 counts as 1 ms
+        let rates = rate.update([530, 500, 480, 500, 500, 500, 509], 0);
+        assert_eq!(rates, [10, 0, 0, 0, 0, 0, 0]);
+
+        rate.reset();
+        assert_eq!(rate.update([0; 7], 10), [0; 7]);
+    }
+
+    #[test]
+    fn press_order_splits_new_keys_by_read() {
+        let order = PressOrder::new(
+            KeyMask(0b000_0001),
+            KeyMask(0b000_0011),
+            KeyMask(0b001_0011),
+        );
+        assert_eq!(order.earlier, KeyMask(0b000_0010));
+        assert_eq!(order.later, KeyMask(0b001_0000));
+
+        // Keys released again before the second read are not ordered
+        let order = PressOrder::new(KeyMask(0), KeyMask(0b000_0100), KeyMask(0));
+        assert_eq!(order, PressOrder::default());
+    }
+
+    #[test]
+    fn noise_stats_keep_an_exact_mean_over_many_samples() {
+        let mut stats = NoiseStats::new();
+        assert_eq!(stats.mean(), None);
+        assert_eq!(stats.min(), None);
+        assert_eq!(stats.max(), None);
+
+        // The sum exceeds u32::MAX after about 65538 samples
+        for sample in 0..200_000u32 {
+            stats.push(if sample % 2 == 0 { 0xFFFF } else { 0xFFFD });
+        }
+        assert_eq!(stats.count(), 200_000);
+        assert_eq!(stats.mean(), Some(0xFFFE));
+        assert_eq!(stats.min(), Some(0xFFFD));
+        assert_eq!(stats.max(), Some(0xFFFF));
+    }
+}
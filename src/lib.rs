@@ -1,20 +1,151 @@
-#![no_std]
+#![cfg_attr(not(feature = "std"), no_std)]
 #![allow(dead_code)]
 
+use embedded_hal::blocking::delay::DelayMs;
 use embedded_hal::blocking::i2c;
 
+mod config;
+mod device;
+mod error;
+mod helpers;
+#[cfg(test)]
+mod mock;
 mod register_map;
-use crate::register_map::Register::*;
+use crate::register_map::Register::{
+    AveAksKey, Calibrate, ChipID, DIKey, FoMcGuard, NthrKey, Reset,
+};
+pub use config::*;
+use core::ops::Index;
 use core::time::Duration;
+pub use device::*;
+pub use error::*;
+pub use helpers::*;
 pub use register_map::*;
 
 // http://ww1.microchip.com/downloads/en/DeviceDoc/Atmel-9596-AT42-QTouch-BSW-AT42QT1070_Datasheet.pdf
 // Chapter 4.2
 const AT42QT1070_I2C_ADDR: u8 = 0x1B;
 
+/// Signals outside of this range are considered to come from an open sense line
+pub const OPEN_CHANNEL_SIGNAL_MIN: u16 = 16;
+pub const OPEN_CHANNEL_SIGNAL_MAX: u16 = 0x3FFF;
+
+/// Reading these registers (0x02 and 0x03) releases the CHANGE line, see chapter 2.7
+pub const CHANGE_CLEARING_REGISTERS: [Register; 2] =
+    [Register::DetectionStatus, Register::KeyStatus];
+
+/// Number of signal samples taken by recommend_threshold
+pub const NOISE_SAMPLES: u16 = 32;
+
+/// Checks for a device answering with the expected chip ID without taking ownership of the bus
+pub fn probe<I2C, E>(i2c: &mut I2C) -> Result<bool, E>
+where
+    I2C: i2c::WriteRead<Error = E>,
+{
+    let mut chip_id = [0u8; 1];
+    i2c.write_read(
+        AT42QT1070_I2C_ADDR,
+        &[RegisterMap::get_register_addr(&ChipID)],
+        &mut chip_id,
+    )?;
+
+    // 0xFF and 0x00 (no device on the bus) never equal the expected ID
+    Ok(chip_id[0] == ChipId::default().as_byte())
+}
+
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub status: DetectionStatus,
+    pub keys: KeyMask,
+    pub signals: [u16; 7],
+    #[cfg(feature = "reference-data")]
+    pub references: [u16; 7],
+}
+
+#[cfg(feature = "std")]
+impl Frame {
+    /// Writes one line: `calibrate,overflow,touch,keys,signal0..signal6[,reference0..reference6]`
+    pub fn write_record(&self, w: &mut impl core::fmt::Write) -> core::fmt::Result {
+        write!(
+            w,
+            "{},{},{},{}",
+            self.status.calibrate as u8,
+            self.status.overflow as u8,
+            self.status.touch as u8,
+            self.keys.0
+        )?;
+        for signal in self.signals.iter() {
+            write!(w, ",{}", signal)?;
+        }
+        #[cfg(feature = "reference-data")]
+        for reference in self.references.iter() {
+            write!(w, ",{}", reference)?;
+        }
+
+        writeln!(w)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CalibrationResult {
+    Completed,
+    /// Keys reported as touched right after calibration, their baseline is likely wrong
+    TouchedDuringCalibration(KeyMask),
+    TimedOut,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AcquisitionState {
+    Idle,
+    Calibrating,
+    Overflow,
+    Touch(KeyMask),
+}
+
+impl AcquisitionState {
+    /// Calibration takes precedence over overflow, which takes precedence over touches
+    pub fn from_status(status: &DetectionStatus, keys: KeyMask) -> Self {
+        if status.calibrate {
+            AcquisitionState::Calibrating
+        } else if status.overflow {
+            AcquisitionState::Overflow
+        } else if !keys.is_empty() {
+            AcquisitionState::Touch(keys)
+        } else {
+            AcquisitionState::Idle
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Read,
+    Write,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct TraceEvent {
+    pub address: u8,
+    pub register: u8,
+    pub direction: Direction,
+    pub value: u8,
+}
+
 pub struct At42qt1070<I2C> {
     i2c: I2C,
+    address: u8,
     register_map: RegisterMap,
+    sync_generation: u32,
+    cache_stale: bool,
+    trace_hook: Option<fn(TraceEvent)>,
+    transaction_gap: Option<(u32, fn(u32))>,
+    auto_wake: bool,
+    wake_interval: Option<u8>,
+    coherent_reads: bool,
+    baseline: [u16; 7],
+    last_change_ms: u32,
+    valid: [bool; REGISTER_COUNT as usize],
+    synced: bool,
 }
 
 impl<I2C, E> At42qt1070<I2C>
@@ -23,17 +154,159 @@ where
 {
     pub fn new(i2c: I2C) -> At42qt1070<I2C> {
         let register_map = RegisterMap::default();
-        At42qt1070 { i2c, register_map }
+        At42qt1070 {
+            i2c,
+            address: AT42QT1070_I2C_ADDR,
+            register_map,
+            sync_generation: 0,
+            cache_stale: false,
+            trace_hook: None,
+            transaction_gap: None,
+            auto_wake: false,
+            wake_interval: None,
+            coherent_reads: false,
+            baseline: [0; 7],
+            last_change_ms: 0,
+            valid: [false; REGISTER_COUNT as usize],
+            synced: false,
+        }
+    }
+
+    /// For boards that translate the address. Expects the 7 bit address, the 8 bit
+    /// addresses of the device (0x36 and 0x37) are rejected with EightBitAddress.
+    pub fn new_with_address(i2c: I2C, address: u8) -> Result<At42qt1070<I2C>, Error<E>> {
+        match address {
+            0x36 | 0x37 => return Err(Error::EightBitAddress(address)),
+            0x80..=0xFF => return Err(Error::InvalidArgument),
+            _ => {}
+        }
+
+        let mut sensor = At42qt1070::new(i2c);
+        sensor.address = address;
+
+        Ok(sensor)
     }
 
     pub fn release(self) -> I2C {
         self.i2c
     }
 
+    /// For ad hoc transactions with other devices on the bus. Transactions with the sensor
+    /// through this reference bypass the cache, sync afterwards if they changed registers.
+    pub fn i2c_mut(&mut self) -> &mut I2C {
+        &mut self.i2c
+    }
+
     pub fn device_reg(&self, reg: &Register) -> u8 {
         self.register_map.reg_as_byte(reg)
     }
 
+    /// Generic write of a raw register value, updates the cache on success
+    pub fn write_register(&mut self, reg: &Register, value: u8) -> Result<(), Error<E>> {
+        if reg.is_read_only() {
+            return Err(Error::ReadOnly(*reg));
+        }
+
+        self.write_reg(RegisterMap::get_register_addr(reg), value)
+            .map_err(Error::I2c)?;
+        self.register_map.set_reg(reg, value);
+
+        Ok(())
+    }
+
+    /// Like write_register, but reads the register back and only updates the cache if the
+    /// device holds the written value. One write and one read, cheaper than verify_config.
+    /// Calibrate and Reset are rejected with `InvalidArgument`, the device clears them on its
+    /// own so the read back value can not be compared.
+    pub fn write_register_confirmed(&mut self, reg: &Register, value: u8) -> Result<(), Error<E>> {
+        if reg.is_read_only() {
+            return Err(Error::ReadOnly(*reg));
+        }
+        if matches!(reg, Calibrate | Reset) {
+            return Err(Error::InvalidArgument);
+        }
+
+        let addr = RegisterMap::get_register_addr(reg);
+        self.write_reg(addr, value).map_err(Error::I2c)?;
+        let actual = self.read_reg(addr).map_err(Error::I2c)?;
+        if actual != value {
+            return Err(Error::VerifyMismatch {
+                register: *reg,
+                expected: value,
+                actual,
+            });
+        }
+        self.register_map.set_reg(reg, value);
+
+        Ok(())
+    }
+
+    /// Incremented on every successful read from the device, wrapping on overflow.
+    /// Compare two values to find out whether the cache was refreshed in between.
+    pub fn sync_generation(&self) -> u32 {
+        self.sync_generation
+    }
+
+    /// Meant to be called from the CHANGE line interrupt: the device signals new data,
+    /// so the cache is outdated until the next sync_all or poll
+    pub fn mark_cache_stale(&mut self) {
+        self.cache_stale = true;
+    }
+
+    pub fn is_cache_stale(&self) -> bool {
+        self.cache_stale
+    }
+
+    /// Called with every register byte read from or written to the device.
+    /// Only plain function pointers are accepted as there is no allocator to box a
+    /// capturing closure, non-capturing closures coerce to them.
+    pub fn set_trace_hook(&mut self, hook: fn(TraceEvent)) {
+        self.trace_hook = Some(hook);
+    }
+
+    pub fn clear_trace_hook(&mut self) {
+        self.trace_hook = None;
+    }
+
+    /// Calls `delay(gap_ns)` before every transaction, for I2C implementations that fail on
+    /// back-to-back transactions. Like the trace hook it takes a plain function pointer, as
+    /// storing a delay implementation would need another type parameter on the driver.
+    /// A gap of 0 removes the pacing.
+    pub fn set_transaction_gap_ns(&mut self, gap_ns: u32, delay: fn(u32)) {
+        self.transaction_gap = if gap_ns == 0 {
+            None
+        } else {
+            Some((gap_ns, delay))
+        };
+    }
+
+    /// In low power mode the first transaction after a long idle may be NACKed while the device
+    /// wakes up. When enabled every read is retried exactly once on any I2C error, as the
+    /// embedded-hal error type does not allow telling a NACK apart. Disabled by default.
+    pub fn set_auto_wake(&mut self, enabled: bool) {
+        self.auto_wake = enabled;
+    }
+
+    /// The MS and LS byte of a signal may be updated by the device in between, tearing the value.
+    /// When enabled 16 bit reads verify the MS byte with an additional transaction and are
+    /// repeated (up to three times) if it changed. If the MS byte changed on all three attempts
+    /// the last pair is returned as is, so a torn value stays possible under constant change.
+    /// Disabled by default.
+    pub fn set_coherent_reads(&mut self, enabled: bool) {
+        self.coherent_reads = enabled;
+    }
+
+    /// False as long as the cached value is still the default and was never read from the device
+    pub fn is_valid(&self, reg: &Register) -> bool {
+        self.valid[RegisterMap::get_register_addr(reg) as usize]
+    }
+
+    /// False as long as the cache only holds the defaults set by new. Stays true after the
+    /// first successful read, even if the cache is cleared again.
+    pub fn has_synced(&self) -> bool {
+        self.synced
+    }
+
     pub fn wait_calibrated_blocking(&mut self) -> Result<(), E> {
         loop {
             if !self.read_detection_status()?.2 {
@@ -42,12 +315,64 @@ where
         }
     }
 
+    /// Delay free wait after start_calibrate: reads the detection status up to max_iters times,
+    /// returns false if the calibrate bit is still set afterwards
+    pub fn calibrate_poll(&mut self, max_iters: u32) -> Result<bool, Error<E>> {
+        for _ in 0..max_iters {
+            if !self.read_detection_only().map_err(Error::I2c)?.calibrate {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Polls the key status until `key` reads released, `wait_for_release_timeout` bounds the wait
+    pub fn wait_for_release_blocking(&mut self, key: Key) -> Result<(), E> {
+        loop {
+            if !self.read_key_status(key)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Polls every millisecond, returns false when the key is still touched after timeout_ms
+    pub fn wait_for_release_timeout<D: DelayMs<u8>>(
+        &mut self,
+        key: Key,
+        delay: &mut D,
+        timeout_ms: u16,
+    ) -> Result<bool, E> {
+        for _ in 0..timeout_ms {
+            if !self.read_key_status(key)? {
+                return Ok(true);
+            }
+            delay.delay_ms(1);
+        }
+
+        Ok(!self.read_key_status(key)?)
+    }
+
     pub fn set_negative_threshold(&mut self, threshold: u8, key: Key) -> Result<(), E> {
         self.write_reg_map_reg(&NthrKey(key), threshold)?;
         *self.register_map.get_nthr_key_register_mut(&key) = threshold;
         Ok(())
     }
 
+    /// Same register as set_negative_threshold: a key triggers once its signal drops `counts`
+    /// below its reference, i.e. reference - signal >= nthr (for DI consecutive acquisitions)
+    pub fn set_trigger_margin(&mut self, key: Key, counts: u8) -> Result<(), E> {
+        self.set_negative_threshold(counts, key)
+    }
+
+    pub fn set_negative_threshold_confirmed(
+        &mut self,
+        threshold: u8,
+        key: Key,
+    ) -> Result<(), Error<E>> {
+        self.write_register_confirmed(&NthrKey(key), threshold)
+    }
+
     pub fn set_ave_aks(&mut self, ave: u8, aks: u8, key: Key) -> Result<(), E> {
         let value = AveAks { ave, aks }.as_byte();
         self.write_reg_map_reg(&AveAksKey(key), value)?;
@@ -58,6 +383,16 @@ where
         Ok(())
     }
 
+    /// Fails with `InvalidArgument` if ave or aks do not fit into their bits
+    pub fn set_ave_aks_confirmed(&mut self, ave: u8, aks: u8, key: Key) -> Result<(), Error<E>> {
+        let value = AveAks { ave, aks }
+            .try_as_byte()
+            .ok_or(Error::InvalidArgument)?;
+        self.write_register_confirmed(&AveAksKey(key), value)
+    }
+
+    /// set_ave and set_aks keep the other half of the register from the cache, which is only
+    /// correct after a prior sync. Use the _checked variants when the cache may be stale.
     pub fn set_ave(&mut self, ave: u8, key: Key) -> Result<(), E> {
         let aks = self.read_cached_ave_aks(key).1;
         self.set_ave_aks(ave, aks, key)
@@ -68,6 +403,95 @@ where
         self.set_ave_aks(ave, aks, key)
     }
 
+    /// Writes the same averaging factor to every key, keeping the cached AKS groups
+    pub fn set_uniform_ave(&mut self, ave: u8) -> Result<(), Error<E>> {
+        if !AveAks::is_valid_ave(ave) {
+            return Err(Error::InvalidArgument);
+        }
+
+        for key in 0..7 {
+            self.set_ave(ave, Key::from(key)).map_err(Error::I2c)?;
+        }
+
+        Ok(())
+    }
+
+    /// Like set_ave, but reads the register first so the AKS of the device is kept
+    pub fn set_ave_checked(&mut self, ave: u8, key: Key) -> Result<(), E> {
+        self.sync_one(&AveAksKey(key))?;
+        self.set_ave(ave, key)
+    }
+
+    /// Like set_aks, but reads the register first so the AVE of the device is kept
+    pub fn set_aks_checked(&mut self, aks: u8, key: Key) -> Result<(), E> {
+        self.sync_one(&AveAksKey(key))?;
+        self.set_aks(aks, key)
+    }
+
+    /// Makes `keys` the members of `group`, keys that were in the group before but are
+    /// not listed are moved out of any group. The AVE/AKS block is read first, so the AVE of
+    /// the device is kept, and written back in one transaction. The registers are written in
+    /// any case, but a group with fewer than two members has no effect and is reported as a
+    /// warning.
+    pub fn set_aks_group_members(
+        &mut self,
+        group: AksGroup,
+        keys: &[Key],
+    ) -> Result<Option<ConfigWarning>, E> {
+        let mut ave_aks = self.read_all_ave_aks()?;
+        let mut members = KeySet::default();
+        for (key, (_, aks)) in ave_aks.iter_mut().enumerate() {
+            let key = Key::from(key as u8);
+            if keys.contains(&key) {
+                *aks = group as u8;
+                members.insert(key);
+            } else if group != AksGroup::None && *aks == group as u8 {
+                *aks = AksGroup::None as u8;
+            }
+        }
+
+        let bytes = ave_aks.map(|(ave, aks)| AveAks { ave, aks }.as_byte());
+        self.write_reg_block(RegisterMap::ave_aks_addr(Key::Key0), &bytes)?;
+        for (cached, byte) in self.register_map.ave_aks_key.0.iter_mut().zip(bytes.iter()) {
+            cached.update(*byte);
+        }
+
+        if group != AksGroup::None && members.len() < 2 {
+            return Ok(Some(ConfigWarning::GroupTooSmall(group)));
+        }
+
+        Ok(None)
+    }
+
+    /// Writes the NTHR, AVE/AKS and DI blocks in three transactions. Every config is
+    /// validated first, so an invalid one leaves the device untouched.
+    pub fn set_all_key_configs(&mut self, configs: &[KeyConfig; 7]) -> Result<(), Error<E>> {
+        if !configs.iter().all(KeyConfig::is_valid) {
+            return Err(Error::InvalidArgument);
+        }
+
+        let mut nthr = [0u8; 7];
+        let mut ave_aks = [0u8; 7];
+        let mut di = [0u8; 7];
+        for key in 0..7 {
+            nthr[key] = configs[key].nthr;
+            ave_aks[key] = configs[key].ave_aks.as_byte();
+            di[key] = configs[key].di;
+        }
+
+        self.write_reg_block(RegisterMap::nthr_addr(Key::Key0), &nthr)
+            .map_err(Error::I2c)?;
+        self.register_map.nthr_key = PerKey(nthr);
+        self.write_reg_block(RegisterMap::ave_aks_addr(Key::Key0), &ave_aks)
+            .map_err(Error::I2c)?;
+        self.register_map.ave_aks_key = PerKey(configs.map(|cfg| cfg.ave_aks));
+        self.write_reg_block(RegisterMap::di_addr(Key::Key0), &di)
+            .map_err(Error::I2c)?;
+        self.register_map.di_key = PerKey(di);
+
+        Ok(())
+    }
+
     pub fn set_detection_integrator(&mut self, di: u8, key: Key) -> Result<(), E> {
         self.write_reg_map_reg(&DIKey(key), di)?;
         *self.register_map.get_di_key_register_mut(&key) = di;
@@ -75,22 +499,69 @@ where
         Ok(())
     }
 
+    pub fn set_detection_integrator_confirmed(&mut self, di: u8, key: Key) -> Result<(), Error<E>> {
+        self.write_register_confirmed(&DIKey(key), di)
+    }
+
     pub fn set_fo_mc_guard(
         &mut self,
         fast_out: bool,
         max_cal: bool,
         guard_channel: Option<Key>,
     ) -> Result<(), E> {
-        let guard_channel = match guard_channel {
-            Some(key) => key as u8,
-            None => 0x07,
-        };
-        let value = FastOutDiMaxCalGuardChannel {
-            fast_out,
-            max_cal,
-            guard_channel,
-        }
-        .as_byte();
+        let value = fo_mc_guard_byte(fast_out, max_cal, guard_channel);
+
+        self.write_reg_map_reg(&FoMcGuard, value)?;
+        self.register_map.fo_mc_guard.update(value);
+
+        Ok(())
+    }
+
+    pub fn set_fo_mc_guard_confirmed(
+        &mut self,
+        fast_out: bool,
+        max_cal: bool,
+        guard_channel: Option<Key>,
+    ) -> Result<(), Error<E>> {
+        let value = fo_mc_guard_byte(fast_out, max_cal, guard_channel);
+
+        self.write_register_confirmed(&FoMcGuard, value)
+    }
+
+    pub fn set_guard_channel(&mut self, guard_channel: Option<Key>) -> Result<(), Error<E>> {
+        let mut cfg = DeviceConfig::from(&self.register_map);
+        let FastOutDiMaxCalGuardChannel {
+            fast_out, max_cal, ..
+        } = cfg.fo_mc_guard;
+        let value = fo_mc_guard_byte(fast_out, max_cal, guard_channel);
+        cfg.fo_mc_guard.update(value);
+        cfg.check().map_err(Error::InvalidConfig)?;
+
+        self.modify_fo_mc_guard(|fo_mc_guard| fo_mc_guard.update(value))
+            .map_err(Error::I2c)
+    }
+
+    /// Warning: while FastOut is enabled the detection integrator is fixed to 1,
+    /// so the per-key values set with `set_detection_integrator` have no effect
+    pub fn set_fast_out(&mut self, enabled: bool) -> Result<(), E> {
+        self.modify_fo_mc_guard(|fo_mc_guard| fo_mc_guard.fast_out = enabled)
+    }
+
+    /// MaxCal only affects recalibration after a max on duration timeout: when set just the
+    /// timed out key recalibrates, when cleared all keys do. `start_calibrate` always
+    /// recalibrates every key regardless of this bit.
+    pub fn set_max_cal(&mut self, enabled: bool) -> Result<(), E> {
+        self.modify_fo_mc_guard(|fo_mc_guard| fo_mc_guard.max_cal = enabled)
+    }
+
+    // Read-modify-write on the cached value of 0x35, the only path the single field setters use
+    fn modify_fo_mc_guard(
+        &mut self,
+        f: impl FnOnce(&mut FastOutDiMaxCalGuardChannel),
+    ) -> Result<(), E> {
+        let mut fo_mc_guard = self.register_map.fo_mc_guard;
+        f(&mut fo_mc_guard);
+        let value = fo_mc_guard.as_byte();
 
         self.write_reg_map_reg(&FoMcGuard, value)?;
         self.register_map.fo_mc_guard.update(value);
@@ -100,22 +571,91 @@ where
 
     pub fn set_low_power_mode(&mut self, interval: Duration) -> Result<(), E> {
         let duration = (interval.as_millis() / 8) as u8;
-        self.write_reg_map_reg(&LowPowerMode, duration)?;
+        self.write_reg_map_reg(&Register::LowPowerMode, duration)?;
         self.register_map.low_power_mode.update(duration);
         Ok(())
     }
 
+    pub fn set_low_power_mode_confirmed(&mut self, interval: Duration) -> Result<(), Error<E>> {
+        let duration = (interval.as_millis() / 8) as u8;
+        self.write_register_confirmed(&Register::LowPowerMode, duration)
+    }
+
+    /// Sets the acquisition interval to the 8 ms multiple closest to 1 / hz. Accepts 1 to 125 Hz
+    /// and returns the achieved frequency in millihertz, e.g. 60 Hz gives 62500 (16 ms).
+    pub fn set_poll_frequency(&mut self, hz: u16) -> Result<u32, Error<E>> {
+        if hz == 0 || hz > 125 {
+            return Err(Error::InvalidArgument);
+        }
+
+        // 125 Hz is one 8 ms step
+        let steps = ((125 + hz / 2) / hz) as u8;
+        self.write_reg_map_reg(&Register::LowPowerMode, steps)
+            .map_err(Error::I2c)?;
+        self.register_map.low_power_mode.update(steps);
+
+        Ok(125_000 / steps as u32)
+    }
+
+    /// Remembers the cached low power mode and writes 255 to it, the longest interval (2040 ms)
+    /// and thereby the lowest current. All other registers are left untouched.
+    pub fn sleep(&mut self) -> Result<(), E> {
+        let current = self.register_map.low_power_mode.as_byte();
+        self.write_reg_map_reg(&Register::LowPowerMode, 0xFF)?;
+        self.register_map.low_power_mode.update(0xFF);
+        if self.wake_interval.is_none() {
+            self.wake_interval = Some(current);
+        }
+
+        Ok(())
+    }
+
+    /// Writes `interval` to the low power mode register, or with None the value saved by sleep
+    /// (the datasheet default of 16 ms without a prior sleep)
+    pub fn wake_to(&mut self, interval: Option<Duration>) -> Result<(), E> {
+        let value = match interval {
+            Some(duration) => (duration.as_millis() / 8) as u8,
+            None => self
+                .wake_interval
+                .unwrap_or_else(|| LowPowerMode::default().as_byte()),
+        };
+        self.write_reg_map_reg(&Register::LowPowerMode, value)?;
+        self.register_map.low_power_mode.update(value);
+        self.wake_interval = None;
+
+        Ok(())
+    }
+
+    /// Writes 0 to the low power mode register, which set_low_power_mode can not express
+    pub fn set_continuous_acquisition(&mut self) -> Result<(), E> {
+        self.write_reg_map_reg(&Register::LowPowerMode, 0)?;
+        self.register_map.low_power_mode.update(0);
+
+        Ok(())
+    }
+
     pub fn set_max_on_duration(&mut self, interval: Option<Duration>) -> Result<(), E> {
         let interval = match interval {
             Some(duration) => (duration.as_millis() / 160) as u8,
             None => 0,
         };
-        self.write_reg_map_reg(&MaxOnDuration, interval)?;
+        self.write_reg_map_reg(&Register::MaxOnDuration, interval)?;
         self.register_map.max_on_duration.update(interval);
 
         Ok(())
     }
 
+    pub fn set_max_on_duration_confirmed(
+        &mut self,
+        interval: Option<Duration>,
+    ) -> Result<(), Error<E>> {
+        let interval = match interval {
+            Some(duration) => (duration.as_millis() / 160) as u8,
+            None => 0,
+        };
+        self.write_register_confirmed(&Register::MaxOnDuration, interval)
+    }
+
     pub fn start_calibrate(&mut self) -> Result<(), E> {
         self.write_reg_map_reg(&Calibrate, 0x01)?;
         self.register_map.calibrate = 0x01;
@@ -123,6 +663,52 @@ where
         Ok(())
     }
 
+    /// Starts a calibration and polls the calibrate bit every millisecond until it clears
+    pub fn calibrate_checked<D: DelayMs<u8>>(
+        &mut self,
+        delay: &mut D,
+        timeout_ms: u16,
+    ) -> Result<CalibrationResult, Error<E>> {
+        self.start_calibrate().map_err(Error::I2c)?;
+
+        let mut elapsed = 0;
+        loop {
+            delay.delay_ms(1);
+            self.clear_change_line().map_err(Error::I2c)?;
+            if !self.register_map.detection_status.calibrate {
+                break;
+            }
+
+            elapsed += 1;
+            if elapsed >= timeout_ms {
+                return Ok(CalibrationResult::TimedOut);
+            }
+        }
+
+        let touched = KeyMask::from(&self.register_map.key_status);
+        if !touched.is_empty() {
+            return Ok(CalibrationResult::TouchedDuringCalibration(touched));
+        }
+
+        Ok(CalibrationResult::Completed)
+    }
+
+    /// Like `calibrate_checked`, afterwards refreshes the signals and the new references.
+    /// After a timeout nothing is refreshed, the references are still being recalculated.
+    #[cfg(feature = "reference-data")]
+    pub fn calibrate_and_refresh<D: DelayMs<u8>>(
+        &mut self,
+        delay: &mut D,
+        timeout_ms: u16,
+    ) -> Result<CalibrationResult, Error<E>> {
+        let result = self.calibrate_checked(delay, timeout_ms)?;
+        if result != CalibrationResult::TimedOut {
+            self.read_signals_and_references().map_err(Error::I2c)?;
+        }
+
+        Ok(result)
+    }
+
     pub fn start_reset(&mut self) -> Result<(), E> {
         self.write_reg_map_reg(&Reset, 0x01)?;
         self.register_map.reset = 0x01;
@@ -130,6 +716,60 @@ where
         Ok(())
     }
 
+    pub fn apply_config(&mut self, cfg: &DeviceConfig) -> Result<(), Error<E>> {
+        cfg.check().map_err(Error::InvalidConfig)?;
+
+        for reg in DeviceConfig::registers().iter() {
+            if let Some(value) = cfg.reg_as_byte(reg) {
+                self.write_reg_map_reg(reg, value).map_err(Error::I2c)?;
+            }
+        }
+        self.register_map.set_config(cfg);
+
+        Ok(())
+    }
+
+    /// Rewrites every configuration register with its datasheet default, independent of start_reset
+    pub fn reset_to_defaults(&mut self) -> Result<(), E> {
+        let cfg = DeviceConfig::default();
+        for reg in DeviceConfig::registers().iter() {
+            if let Some(value) = cfg.reg_as_byte(reg) {
+                self.write_reg_map_reg(reg, value)?;
+            }
+        }
+        self.register_map.set_config(&cfg);
+
+        Ok(())
+    }
+
+    /// Snapshot of the cached configuration, only reflects the device after a sync
+    pub fn save_state(&self) -> DeviceConfig {
+        DeviceConfig::from(&self.register_map)
+    }
+
+    /// Lints the cached configuration, see DeviceConfig::lint
+    #[cfg(feature = "heapless")]
+    pub fn lint_config(&self) -> heapless::Vec<ConfigWarning, 16> {
+        self.save_state().lint()
+    }
+
+    pub fn restore_state(&mut self, cfg: &DeviceConfig) -> Result<(), Error<E>> {
+        self.apply_config(cfg)?;
+        self.start_calibrate().map_err(Error::I2c)
+    }
+
+    /// Reads back the whole device and compares every writable register against `cfg`
+    pub fn verify_config(&mut self, cfg: &DeviceConfig) -> Result<(), Error<E>> {
+        self.sync_all().map_err(Error::I2c)?;
+
+        let mismatch = DeviceConfig::from(&self.register_map).diff(cfg);
+        if !mismatch.is_empty() {
+            return Err(Error::ConfigMismatch(mismatch));
+        }
+
+        Ok(())
+    }
+
     //0
     pub fn read_cached_chip_id(&self) -> (u8, u8) {
         let major_id = self.register_map.chip_id.major_id;
@@ -144,13 +784,36 @@ where
         Ok(self.read_cached_chip_id())
     }
 
+    /// Like read_chip_id, but fails with NoDevice when the bus returned a sentinel value
+    pub fn read_chip_id_checked(&mut self) -> Result<(u8, u8), Error<E>> {
+        self.sync_one(&ChipID).map_err(Error::I2c)?;
+        if ChipId::is_bus_sentinel(self.register_map.chip_id.as_byte()) {
+            return Err(Error::NoDevice);
+        }
+
+        Ok(self.read_cached_chip_id())
+    }
+
+    /// Liveness check: re-reads the chip ID and compares it with the expected value
+    pub fn verify_identity(&mut self) -> Result<(), Error<E>> {
+        self.sync_one(&ChipID).map_err(Error::I2c)?;
+
+        let expected = ChipId::default().as_byte();
+        let actual = self.register_map.chip_id.as_byte();
+        if actual != expected {
+            return Err(Error::IdentityMismatch { expected, actual });
+        }
+
+        Ok(())
+    }
+
     //1
     pub fn read_cached_firmware_version(&self) -> u8 {
         self.register_map.firmware_version
     }
 
     pub fn read_firmware_version(&mut self) -> Result<u8, E> {
-        self.sync_one(&FirmwareVersion)?;
+        self.sync_one(&Register::FirmwareVersion)?;
 
         Ok(self.register_map.firmware_version)
     }
@@ -165,20 +828,27 @@ where
     }
 
     pub fn read_detection_status(&mut self) -> Result<(bool, bool, bool), E> {
-        self.sync_one(&DetectionStatus)?;
+        self.sync_one(&Register::DetectionStatus)?;
 
         Ok(self.read_cached_detection_status())
     }
 
+    /// Cheapest health poll, reads only the detection status byte
+    pub fn read_detection_only(&mut self) -> Result<DetectionStatus, E> {
+        self.sync_one(&Register::DetectionStatus)?;
+
+        Ok(self.register_map.detection_status)
+    }
+
     //3
     pub fn read_cached_key_status(&self, key: Key) -> bool {
         let status = &self.register_map.key_status;
 
-        status.key[key as usize]
+        status.key[key.index()]
     }
 
     pub fn read_key_status(&mut self, key: Key) -> Result<bool, E> {
-        self.sync_one(&KeyStatus)?;
+        self.sync_one(&Register::KeyStatus)?;
 
         Ok(self.read_cached_key_status(key))
     }
@@ -188,37 +858,214 @@ where
     }
 
     pub fn read_full_key_status(&mut self) -> Result<[bool; 7], E> {
-        self.sync_one(&KeyStatus)?;
+        self.sync_one(&Register::KeyStatus)?;
 
         Ok(self.read_cached_full_key_status())
     }
 
-    //4-17
-    pub fn read_cached_key_signal(&self, key: Key) -> u16 {
-        let ms = self.register_map.get_key_signal_register(&key, true);
-        let ls = self.register_map.get_key_signal_register(&key, false);
-        ((*ms as u16) << 8) | (*ls as u16)
+    /// Fills `out` with the cached pressed keys in ascending order and returns their count
+    pub fn pressed_keys_into(&self, out: &mut [Key; 7]) -> usize {
+        let mut count = 0;
+        for key in KeyMask::from(&self.register_map.key_status).iter() {
+            out[count] = key;
+            count += 1;
+        }
+
+        count
     }
 
-    pub fn read_key_signal(&mut self, key: Key) -> Result<u16, E> {
-        self.sync_one(&KeySignalMs(key))?;
-        self.sync_one(&KeySignalLs(key))?;
+    pub fn read_cached_key_status_byte(&self) -> u8 {
+        self.register_map.key_status.as_byte()
+    }
 
-        Ok(self.read_cached_key_signal(key))
+    pub fn read_key_status_byte(&mut self) -> Result<u8, E> {
+        self.sync_one(&Register::KeyStatus)?;
+
+        Ok(self.read_cached_key_status_byte())
     }
 
-    //18-31
-    pub fn read_cached_reference_data(&self, key: Key) -> u16 {
-        let ms = self.register_map.get_reference_data_register(&key, true);
-        let ls = self.register_map.get_reference_data_register(&key, false);
-        ((*ms as u16) << 8) | (*ls as u16)
+    /// Marks every key as released in the cache only, no bus traffic. The next poll_key_events
+    /// then reports every touched key as pressed. Until then `is_valid(&Register::KeyStatus)`
+    /// is false, the cleared value was not read from the device.
+    pub fn clear_cached_key_status(&mut self) {
+        self.register_map.key_status = KeyStatus::default();
+        self.valid[RegisterMap::get_register_addr(&Register::KeyStatus) as usize] = false;
     }
 
-    pub fn read_reference_data(&mut self, key: Key) -> Result<u16, E> {
-        self.sync_one(&ReferenceDataMs(key))?;
-        self.sync_one(&ReferenceDataLs(key))?;
+    /// Reads the key status and returns the edges relative to the previously cached status
+    pub fn poll_key_events(&mut self) -> Result<KeyEvents, E> {
+        let previous = KeyMask::from(&self.register_map.key_status);
+        self.sync_one(&Register::KeyStatus)?;
 
-        Ok(self.read_cached_reference_data(key))
+        Ok(KeyEvents::new(
+            previous,
+            KeyMask::from(&self.register_map.key_status),
+        ))
+    }
+
+    /// Like poll_key_events, but reads the key status twice in a row to tell which newly pressed
+    /// keys came first. Best effort only: keys pressed between two polls but before the first
+    /// read can not be ordered, the resolution is the time between the two reads.
+    pub fn poll_key_events_ordered(&mut self) -> Result<(KeyEvents, PressOrder), E> {
+        let previous = KeyMask::from(&self.register_map.key_status);
+        self.sync_one(&Register::KeyStatus)?;
+        let first = KeyMask::from(&self.register_map.key_status);
+        self.sync_one(&Register::KeyStatus)?;
+        let second = KeyMask::from(&self.register_map.key_status);
+
+        Ok((
+            KeyEvents::new(previous, second),
+            PressOrder::new(previous, first, second),
+        ))
+    }
+
+    /// Like poll_key_events, additionally remembers `now_ms` (any monotonic millisecond clock)
+    /// as time of the last change if a key changed
+    pub fn poll_key_events_at(&mut self, now_ms: u32) -> Result<KeyEvents, E> {
+        let previous = KeyMask::from(&self.register_map.key_status);
+        let events = self.poll_key_events()?;
+        if KeyMask::from(&self.register_map.key_status) != previous {
+            self.last_change_ms = now_ms;
+        }
+
+        Ok(events)
+    }
+
+    /// Time since the last change seen by poll_key_events_at, or since 0 without any change
+    pub fn ms_since_last_change(&self, now_ms: u32) -> u32 {
+        now_ms.wrapping_sub(self.last_change_ms)
+    }
+
+    /// Pushes the edges since the last key status read into `producer`, the interrupt half of an
+    /// EventSink. Returns the number of events which did not fit.
+    #[cfg(feature = "heapless")]
+    pub fn push_key_events<const N: usize>(
+        &mut self,
+        producer: &mut EventProducer<'_, N>,
+    ) -> Result<usize, E> {
+        let mut dropped = 0;
+        for event in self.poll_key_events()? {
+            if producer.enqueue(event).is_err() {
+                dropped += 1;
+            }
+        }
+
+        Ok(dropped)
+    }
+
+    //4-17
+    pub fn read_cached_key_signal(&self, key: Key) -> u16 {
+        let ms = self.register_map.get_key_signal_register(&key, true);
+        let ls = self.register_map.get_key_signal_register(&key, false);
+        u16_from_ms_ls(*ms, *ls)
+    }
+
+    pub fn read_key_signal(&mut self, key: Key) -> Result<u16, E> {
+        let (ms, ls) = self.read_ms_ls(RegisterMap::signal_ms_addr(key))?;
+        self.register_map.key_signal_ms[key] = ms;
+        self.register_map.key_signal_ls[key] = ls;
+
+        Ok(self.read_cached_key_signal(key))
+    }
+
+    /// Reads the signals of `keys` into `signals` in the same order and returns how many were
+    /// read. Ascending consecutive keys are read in one transaction, any other set per key.
+    pub fn read_key_signals_for(&mut self, keys: &[Key], signals: &mut [u16]) -> Result<usize, E> {
+        let len = keys.len().min(signals.len());
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let keys = &keys[..len];
+        let contiguous = keys
+            .windows(2)
+            .all(|pair| pair[1].index() == pair[0].index() + 1);
+        if contiguous {
+            let mut buf = [0u8; 14];
+            let buf = &mut buf[..len * 2];
+            self.read_reg_block(RegisterMap::signal_ms_addr(keys[0]), buf)?;
+            for (offset, key) in keys.iter().enumerate() {
+                self.register_map.key_signal_ms[*key] = buf[offset * 2];
+                self.register_map.key_signal_ls[*key] = buf[1 + offset * 2];
+            }
+        } else {
+            for key in keys.iter() {
+                self.read_key_signal(*key)?;
+            }
+        }
+
+        for (signal, key) in signals.iter_mut().zip(keys.iter()) {
+            *signal = self.read_cached_key_signal(*key);
+        }
+
+        Ok(len)
+    }
+
+    /// Reads detection status, key status and all signals (0x02 to 0x11) in one transaction
+    pub fn read_all_key_signals_unchecked(&mut self) -> Result<[u16; 7], E> {
+        let mut buf = [0u8; 16];
+        self.read_reg_block(
+            RegisterMap::get_register_addr(&Register::DetectionStatus),
+            &mut buf,
+        )?;
+
+        self.register_map.detection_status.update(buf[0]);
+        self.register_map.key_status.update(buf[1]);
+
+        Ok(update_u16_block(
+            &mut self.register_map.key_signal_ms,
+            &mut self.register_map.key_signal_ls,
+            &buf[2..],
+        ))
+    }
+
+    /// Stores the current signals as application baseline, independent of the device references
+    pub fn capture_baseline(&mut self) -> Result<(), E> {
+        self.baseline = self.read_all_key_signals_unchecked()?;
+
+        Ok(())
+    }
+
+    /// Current signal minus the captured baseline per key
+    pub fn delta_from_baseline(&mut self) -> Result<[i32; 7], E> {
+        let signals = self.read_all_key_signals_unchecked()?;
+
+        let mut deltas = [0i32; 7];
+        for key in 0..7 {
+            deltas[key] = signals[key] as i32 - self.baseline[key] as i32;
+        }
+
+        Ok(deltas)
+    }
+
+    /// Like read_all_key_signals_unchecked, but fails with Overflow when the acquisition
+    /// overflowed and the signals may be invalid
+    pub fn read_all_key_signals(&mut self) -> Result<[u16; 7], SignalError<E>> {
+        let signals = self
+            .read_all_key_signals_unchecked()
+            .map_err(SignalError::I2c)?;
+        if self.register_map.detection_status.overflow {
+            return Err(SignalError::Overflow);
+        }
+
+        Ok(signals)
+    }
+
+    //18-31
+    #[cfg(feature = "reference-data")]
+    pub fn read_cached_reference_data(&self, key: Key) -> u16 {
+        let ms = self.register_map.get_reference_data_register(&key, true);
+        let ls = self.register_map.get_reference_data_register(&key, false);
+        u16_from_ms_ls(*ms, *ls)
+    }
+
+    #[cfg(feature = "reference-data")]
+    pub fn read_reference_data(&mut self, key: Key) -> Result<u16, E> {
+        let (ms, ls) = self.read_ms_ls(RegisterMap::reference_ms_addr(key))?;
+        self.register_map.reference_data_ms[key] = ms;
+        self.register_map.reference_data_ls[key] = ls;
+
+        Ok(self.read_cached_reference_data(key))
     }
 
     //32-38
@@ -232,6 +1079,15 @@ where
         Ok(self.read_cached_negative_threshold(key))
     }
 
+    /// Reads all negative thresholds (0x20 to 0x26) in one transaction
+    pub fn read_all_negative_thresholds(&mut self) -> Result<[u8; 7], E> {
+        let mut buf = [0u8; 7];
+        self.read_reg_block(RegisterMap::nthr_addr(Key::Key0), &mut buf)?;
+        self.register_map.nthr_key = PerKey(buf);
+
+        Ok(buf)
+    }
+
     //39-45
     pub fn read_cached_ave_aks(&self, key: Key) -> (u8, u8) {
         let ave_aks = self.register_map.get_ave_aks_key_register(&key);
@@ -244,6 +1100,20 @@ where
         Ok(self.read_cached_ave_aks(key))
     }
 
+    /// Reads all AVE/AKS registers (0x27 to 0x2D) in one transaction
+    pub fn read_all_ave_aks(&mut self) -> Result<[(u8, u8); 7], E> {
+        let mut buf = [0u8; 7];
+        self.read_reg_block(RegisterMap::ave_aks_addr(Key::Key0), &mut buf)?;
+
+        let mut ave_aks = [(0u8, 0u8); 7];
+        for key in 0..7 {
+            self.register_map.ave_aks_key.0[key].update(buf[key]);
+            ave_aks[key] = self.read_cached_ave_aks(Key::from(key as u8));
+        }
+
+        Ok(ave_aks)
+    }
+
     //46-52
     pub fn read_cached_detection_integrator(&self, key: Key) -> u8 {
         *self.register_map.get_di_key_register(&key)
@@ -255,6 +1125,15 @@ where
         Ok(self.read_cached_detection_integrator(key))
     }
 
+    /// Reads all detection integrators (0x2E to 0x34) in one transaction
+    pub fn read_all_detection_integrators(&mut self) -> Result<[u8; 7], E> {
+        let mut buf = [0u8; 7];
+        self.read_reg_block(RegisterMap::di_addr(Key::Key0), &mut buf)?;
+        self.register_map.di_key = PerKey(buf);
+
+        Ok(buf)
+    }
+
     //53
     pub fn read_cached_fo_mc_guard(&self) -> (bool, bool, u8) {
         let fo_mc_guard = &self.register_map.fo_mc_guard;
@@ -272,16 +1151,22 @@ where
     }
 
     //54
+    /// A zero interval means continuous acquisition
     pub fn read_cached_low_power_mode(&self) -> Duration {
-        let value = self.register_map.low_power_mode.as_byte();
-        if value == 0 {
-            return Duration::from_millis(8);
+        Duration::from_millis(self.register_map.low_power_mode.as_millis() as u64)
+    }
+
+    /// Cached acquisition interval, None while the device acquires continuously
+    pub fn effective_poll_interval_ms(&self) -> Option<u16> {
+        if self.register_map.low_power_mode.is_continuous() {
+            return None;
         }
-        Duration::from_millis(value as u64 * 8)
+
+        Some(self.register_map.low_power_mode.as_millis())
     }
 
     pub fn read_low_power_mode(&mut self) -> Result<Duration, E> {
-        self.sync_one(&LowPowerMode)?;
+        self.sync_one(&Register::LowPowerMode)?;
 
         Ok(self.read_cached_low_power_mode())
     }
@@ -297,61 +1182,384 @@ where
     }
 
     pub fn read_max_on_duration(&mut self) -> Result<Option<Duration>, E> {
-        self.sync_one(&MaxOnDuration)?;
+        self.sync_one(&Register::MaxOnDuration)?;
 
         Ok(self.read_cached_max_on_duration())
     }
 
+    //56
+    pub fn read_cached_calibrate(&self) -> u8 {
+        self.register_map.calibrate
+    }
+
+    pub fn read_calibrate(&mut self) -> Result<u8, E> {
+        self.sync_one(&Calibrate)?;
+
+        Ok(self.read_cached_calibrate())
+    }
+
+    //57
+    pub fn read_cached_reset(&self) -> u8 {
+        self.register_map.reset
+    }
+
+    pub fn read_reset(&mut self) -> Result<u8, E> {
+        self.sync_one(&Reset)?;
+
+        Ok(self.read_cached_reset())
+    }
+
+    /// Reads exactly CHANGE_CLEARING_REGISTERS in one transaction to release the CHANGE line
+    pub fn clear_change_line(&mut self) -> Result<(), E> {
+        let mut buf = [0u8; CHANGE_CLEARING_REGISTERS.len()];
+        self.read_reg_block(
+            RegisterMap::get_register_addr(&CHANGE_CLEARING_REGISTERS[0]),
+            &mut buf,
+        )?;
+
+        self.register_map.detection_status.update(buf[0]);
+        self.register_map.key_status.update(buf[1]);
+
+        Ok(())
+    }
+
+    /// Clears the CHANGE line and samples it with `is_asserted` (e.g. the input pin being low).
+    /// Fails with ChangeLineStuck once the monitor saw too many immediate re-assertions.
+    pub fn clear_change_line_monitored(
+        &mut self,
+        monitor: &mut ChangeLineMonitor,
+        is_asserted: impl FnOnce() -> bool,
+    ) -> Result<(), Error<E>> {
+        self.clear_change_line().map_err(Error::I2c)?;
+        if monitor.update(is_asserted()) {
+            return Err(Error::ChangeLineStuck);
+        }
+
+        Ok(())
+    }
+
+    /// Reads detection and key status in one transaction, which also releases the CHANGE line
+    pub fn acquisition_state(&mut self) -> Result<AcquisitionState, E> {
+        self.clear_change_line()?;
+
+        Ok(AcquisitionState::from_status(
+            &self.register_map.detection_status,
+            KeyMask::from(&self.register_map.key_status),
+        ))
+    }
+
+    /// Reads detection status, key status, signals and references (0x02 to 0x1F) in one transaction,
+    /// without the reference-data feature the block ends at 0x11
+    pub fn poll(&mut self) -> Result<Frame, E> {
+        #[cfg(feature = "reference-data")]
+        let mut buf = [0u8; 30];
+        #[cfg(not(feature = "reference-data"))]
+        let mut buf = [0u8; 16];
+        self.read_reg_block(
+            RegisterMap::get_register_addr(&Register::DetectionStatus),
+            &mut buf,
+        )?;
+
+        self.register_map.detection_status.update(buf[0]);
+        self.register_map.key_status.update(buf[1]);
+        let signals = update_u16_block(
+            &mut self.register_map.key_signal_ms,
+            &mut self.register_map.key_signal_ls,
+            &buf[2..16],
+        );
+        self.cache_stale = false;
+
+        Ok(Frame {
+            status: self.register_map.detection_status,
+            keys: KeyMask::from(&self.register_map.key_status),
+            signals,
+            #[cfg(feature = "reference-data")]
+            references: update_u16_block(
+                &mut self.register_map.reference_data_ms,
+                &mut self.register_map.reference_data_ls,
+                &buf[16..30],
+            ),
+        })
+    }
+
+    /// Reads the signal and reference blocks (0x04 to 0x1F) in one transaction
+    #[cfg(feature = "reference-data")]
+    pub fn read_signals_and_references(&mut self) -> Result<([u16; 7], [u16; 7]), E> {
+        let mut buf = [0u8; 28];
+        self.read_reg_block(RegisterMap::signal_ms_addr(Key::Key0), &mut buf)?;
+
+        let signals = update_u16_block(
+            &mut self.register_map.key_signal_ms,
+            &mut self.register_map.key_signal_ls,
+            &buf[..14],
+        );
+        let references = update_u16_block(
+            &mut self.register_map.reference_data_ms,
+            &mut self.register_map.reference_data_ls,
+            &buf[14..],
+        );
+
+        Ok((signals, references))
+    }
+
+    /// Reads the reference block (0x12 to 0x1F) in one transaction
+    #[cfg(feature = "reference-data")]
+    pub fn sync_reference_data(&mut self) -> Result<[u16; 7], E> {
+        let mut buf = [0u8; 14];
+        self.read_reg_block(RegisterMap::reference_ms_addr(Key::Key0), &mut buf)?;
+
+        Ok(update_u16_block(
+            &mut self.register_map.reference_data_ms,
+            &mut self.register_map.reference_data_ls,
+            &buf,
+        ))
+    }
+
+    /// Returns the touched key with the largest reference - signal delta
+    #[cfg(feature = "reference-data")]
+    pub fn strongest_touched_key(&mut self) -> Result<Option<Key>, E> {
+        let (signals, references) = self.read_signals_and_references()?;
+        let touched = self.read_full_key_status()?;
+
+        let mut strongest: Option<(Key, i32)> = None;
+        for key in 0..7 {
+            if !touched[key] {
+                continue;
+            }
+            let delta = references[key] as i32 - signals[key] as i32;
+            match strongest {
+                Some((_, max)) if max >= delta => {}
+                _ => strongest = Some((Key::from(key as u8), delta)),
+            }
+        }
+
+        Ok(strongest.map(|(key, _)| key))
+    }
+
+    /// Interpolated position of a touch on a slider made of the seven pads in key order,
+    /// 0 at Key0 and 1000 at Key6. None unless a delta exceeds the negative threshold of its key.
+    #[cfg(feature = "reference-data")]
+    pub fn slider_position(&mut self) -> Result<Option<u16>, E> {
+        let (signals, references) = self.read_signals_and_references()?;
+
+        let mut deltas = [0u16; 7];
+        for key in 0..7 {
+            deltas[key] = references[key].saturating_sub(signals[key]);
+        }
+
+        Ok(slider_position(&deltas, &self.register_map.nthr_key.0))
+    }
+
+    /// Headroom per key before a touch triggers: nthr - (reference - signal),
+    /// negative once the delta exceeds the threshold
+    #[cfg(feature = "reference-data")]
+    pub fn margins(&mut self) -> Result<[i32; 7], E> {
+        let (signals, references) = self.read_signals_and_references()?;
+        let thresholds = self.read_all_negative_thresholds()?;
+
+        let mut margins = [0i32; 7];
+        for key in 0..7 {
+            let delta = references[key] as i32 - signals[key] as i32;
+            margins[key] = thresholds[key] as i32 - delta;
+        }
+
+        Ok(margins)
+    }
+
+    /// A key touched during calibration ends up with a reference below its untouched signal, so
+    /// once released its signal exceeds the reference by more than the threshold. Such keys, or a
+    /// zero reference, trigger a new calibration, but only while no key reports a touch.
+    /// Returns whether a calibration was started.
+    #[cfg(feature = "reference-data")]
+    pub fn recover_if_miscalibrated(&mut self) -> Result<bool, Error<E>> {
+        let (signals, references) = self.read_signals_and_references().map_err(Error::I2c)?;
+        let thresholds = self.read_all_negative_thresholds().map_err(Error::I2c)?;
+
+        let miscalibrated = (0..7).any(|key| {
+            references[key] == 0
+                || signals[key].saturating_sub(references[key]) > thresholds[key] as u16
+        });
+        if !miscalibrated {
+            return Ok(false);
+        }
+
+        self.clear_change_line().map_err(Error::I2c)?;
+        if !KeyMask::from(&self.register_map.key_status).is_empty() {
+            return Ok(false);
+        }
+
+        self.start_calibrate().map_err(Error::I2c)?;
+
+        Ok(true)
+    }
+
+    /// Signal in per mille of the reference, 0 while the reference is 0
+    #[cfg(feature = "reference-data")]
+    pub fn signal_ratio(&mut self, key: Key) -> Result<u16, E> {
+        let signal = self.read_key_signal(key)? as u32;
+        let reference = self.read_reference_data(key)? as u32;
+        if reference == 0 {
+            return Ok(0);
+        }
+
+        Ok((signal * 1000 / reference).min(u16::MAX as u32) as u16)
+    }
+
+    /// Keys whose reference - signal delta exceeds threshold, independent of the key status.
+    /// Under AKS only one key of a group reports, this reveals the suppressed ones as well.
+    #[cfg(feature = "reference-data")]
+    pub fn raw_signals_indicate_multitouch(&mut self, threshold: u16) -> Result<KeyMask, E> {
+        let (signals, references) = self.read_signals_and_references()?;
+
+        let mut touched = KeyMask::default();
+        for key in 0..7 {
+            if references[key].saturating_sub(signals[key]) > threshold {
+                touched.insert(Key::from(key as u8));
+            }
+        }
+
+        Ok(touched)
+    }
+
+    /// Heuristic for production tests: flags keys whose signal lies outside of
+    /// OPEN_CHANNEL_SIGNAL_MIN..=OPEN_CHANNEL_SIGNAL_MAX or whose reference is zero
+    #[cfg(feature = "reference-data")]
+    pub fn detect_open_channels(&mut self) -> Result<KeyMask, E> {
+        let (signals, references) = self.read_signals_and_references()?;
+
+        let mut open = KeyMask::default();
+        for key in 0..7 {
+            if !(OPEN_CHANNEL_SIGNAL_MIN..=OPEN_CHANNEL_SIGNAL_MAX).contains(&signals[key])
+                || references[key] == 0
+            {
+                open.insert(Key::from(key as u8));
+            }
+        }
+
+        Ok(open)
+    }
+
+    /// Spread (max - min) of the references of all keys. Identical pads calibrate to similar
+    /// references, so a small score is good. What counts as large depends on the pad design:
+    /// compare against the score of a known good board, a jump usually means a bad pad.
+    #[cfg(feature = "reference-data")]
+    pub fn calibration_quality(&mut self) -> Result<u16, E> {
+        let (_, references) = self.read_signals_and_references()?;
+
+        let min = references.iter().min().copied().unwrap_or_default();
+        let max = references.iter().max().copied().unwrap_or_default();
+
+        Ok(max - min)
+    }
+
+    /// Samples the signal of an untouched key. Each sample reads MS and LS in one transaction.
+    pub fn measure_noise(&mut self, key: Key, samples: u16) -> Result<NoiseBand, E> {
+        let mut band = NoiseBand::default();
+        for _ in 0..samples {
+            band.add(self.read_key_signal(key)?);
+        }
+
+        Ok(band)
+    }
+
+    /// Suggests a negative threshold of peak-to-peak noise plus margin, the key must not be touched
+    pub fn recommend_threshold(&mut self, key: Key, margin: u8) -> Result<u8, E> {
+        Ok(self
+            .measure_noise(key, NOISE_SAMPLES)?
+            .recommend_threshold(margin))
+    }
+
+    /// Reads the inclusive address range start..=end in one transaction into the cache
+    pub fn sync_range(&mut self, start: u8, end: u8) -> Result<(), E> {
+        let end = end.min(REGISTER_COUNT - 1);
+        if start > end {
+            return Ok(());
+        }
+
+        let mut buf = [0u8; REGISTER_COUNT as usize];
+        let buf = &mut buf[..=(end - start) as usize];
+        self.read_reg_block(start, buf)?;
+        for (offset, value) in buf.iter().enumerate() {
+            if let Some(reg) = Register::from_addr(start + offset as u8) {
+                self.register_map.update_reg(&reg, *value);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the raw bytes of `regs` into `out` in the same order. Runs of ascending adjacent
+    /// addresses are coalesced into one transaction, the cache is updated for every register.
+    pub fn read_registers(&mut self, regs: &[Register], out: &mut [u8]) -> Result<(), E> {
+        let len = regs.len().min(out.len());
+        let mut start = 0;
+        while start < len {
+            let mut end = start + 1;
+            while end < len && regs[end].addr() == regs[end - 1].addr().wrapping_add(1) {
+                end += 1;
+            }
+
+            self.read_reg_block(regs[start].addr(), &mut out[start..end])?;
+            for (reg, value) in regs[start..end].iter().zip(out[start..end].iter()) {
+                self.register_map.update_reg(reg, *value);
+            }
+            start = end;
+        }
+
+        Ok(())
+    }
+
+    /// Like sync_all, but also returns a snapshot of the freshly read registers
+    pub fn read_all(&mut self) -> Result<RegisterMap, E> {
+        self.sync_all()?;
+
+        Ok(self.register_map.clone())
+    }
+
     pub fn sync_all(&mut self) -> Result<(), E> {
         let new = self.read_all_reg()?;
 
-        self.register_map
-            .chip_id
-            .update(new[RegisterMap::get_register_addr(&ChipID) as usize]);
-        self.register_map.firmware_version =
-            new[RegisterMap::get_register_addr(&FirmwareVersion) as usize];
-        self.register_map
-            .detection_status
-            .update(new[RegisterMap::get_register_addr(&DetectionStatus) as usize]);
-        self.register_map
-            .key_status
-            .update(new[RegisterMap::get_register_addr(&KeyStatus) as usize]);
-        for key in 0..7 {
-            *self
-                .register_map
-                .get_key_signal_register_mut(&Key::from(key), true) =
-                new[RegisterMap::get_register_addr(&KeySignalMs(Key::from(key))) as usize];
-            *self
-                .register_map
-                .get_key_signal_register_mut(&Key::from(key), false) =
-                new[RegisterMap::get_register_addr(&KeySignalLs(Key::from(key))) as usize];
-            *self
-                .register_map
-                .get_reference_data_register_mut(&Key::from(key), true) =
-                new[RegisterMap::get_register_addr(&ReferenceDataMs(Key::from(key))) as usize];
-            *self
-                .register_map
-                .get_reference_data_register_mut(&Key::from(key), false) =
-                new[RegisterMap::get_register_addr(&ReferenceDataLs(Key::from(key))) as usize];
-            *self.register_map.get_nthr_key_register_mut(&Key::from(key)) =
-                new[RegisterMap::get_register_addr(&NthrKey(Key::from(key))) as usize];
-            self.register_map
-                .get_ave_aks_key_register_mut(&Key::from(key))
-                .update(new[RegisterMap::get_register_addr(&AveAksKey(Key::from(key))) as usize]);
-            *self.register_map.get_di_key_register_mut(&Key::from(key)) =
-                new[RegisterMap::get_register_addr(&DIKey(Key::from(key))) as usize];
+        self.register_map = RegisterMap::from_bytes(&new);
+        self.cache_stale = false;
+
+        Ok(())
+    }
+
+    /// Writes the configuration registers (0x20 to 0x37) of a captured register image in one
+    /// transaction. Read-only registers as well as Calibrate and Reset are skipped.
+    pub fn write_image(&mut self, image: &[u8; REGISTER_COUNT as usize]) -> Result<(), E> {
+        let start = RegisterMap::get_register_addr(&NthrKey(Key::Key0));
+        self.write_reg_block(
+            start,
+            &image[start as usize..start as usize + CONFIG_REGISTER_COUNT],
+        )?;
+
+        let cfg = DeviceConfig::from(&RegisterMap::from_bytes(image));
+        self.register_map.set_config(&cfg);
+
+        Ok(())
+    }
+
+    /// The configuration registers (0x20 to 0x37) in address order, read in one transaction.
+    /// These are all 24 writable registers except Calibrate and Reset.
+    pub fn read_config_bytes(&mut self) -> Result<[u8; CONFIG_REGISTER_COUNT], E> {
+        let start = RegisterMap::nthr_addr(Key::Key0);
+        self.sync_range(start, start + CONFIG_REGISTER_COUNT as u8 - 1)?;
+
+        let mut bytes = [0u8; CONFIG_REGISTER_COUNT];
+        for (byte, reg) in bytes.iter_mut().zip(DeviceConfig::registers().iter()) {
+            *byte = self.register_map.reg_as_byte(reg);
+        }
+
+        Ok(bytes)
+    }
+
+    /// Counterpart of read_config_bytes, writes all configuration registers in one transaction
+    pub fn write_config_bytes(&mut self, bytes: &[u8; CONFIG_REGISTER_COUNT]) -> Result<(), E> {
+        self.write_reg_block(RegisterMap::nthr_addr(Key::Key0), bytes)?;
+        for (byte, reg) in bytes.iter().zip(DeviceConfig::registers().iter()) {
+            self.register_map.set_reg(reg, *byte);
         }
-        self.register_map
-            .fo_mc_guard
-            .update(new[RegisterMap::get_register_addr(&FoMcGuard) as usize]);
-        self.register_map
-            .low_power_mode
-            .update(new[RegisterMap::get_register_addr(&LowPowerMode) as usize]);
-        self.register_map
-            .max_on_duration
-            .update(new[RegisterMap::get_register_addr(&MaxOnDuration) as usize]);
-        self.register_map.calibrate = new[RegisterMap::get_register_addr(&Calibrate) as usize];
-        self.register_map.reset = new[RegisterMap::get_register_addr(&Reset) as usize];
 
         Ok(())
     }
@@ -382,16 +1590,20 @@ where
                 *self.register_map.get_key_signal_register_mut(key, false) =
                     self.read_reg(RegisterMap::get_register_addr(reg))?
             }
+            #[cfg(feature = "reference-data")]
             Register::ReferenceDataMs(key) => {
                 *self.register_map.get_reference_data_register_mut(key, true) =
                     self.read_reg(RegisterMap::get_register_addr(reg))?
             }
+            #[cfg(feature = "reference-data")]
             Register::ReferenceDataLs(key) => {
                 *self
                     .register_map
                     .get_reference_data_register_mut(key, false) =
                     self.read_reg(RegisterMap::get_register_addr(reg))?
             }
+            #[cfg(not(feature = "reference-data"))]
+            Register::ReferenceDataMs(_) | Register::ReferenceDataLs(_) => {}
             Register::NthrKey(key) => {
                 *self.register_map.get_nthr_key_register_mut(key) =
                     self.read_reg(RegisterMap::get_register_addr(reg))?
@@ -429,31 +1641,84 @@ where
         Ok(())
     }
 
+    fn write_read(&mut self, start_addr: u8, buf: &mut [u8]) -> Result<(), E> {
+        self.pace();
+        let result = self.i2c.write_read(self.address, &[start_addr], buf);
+        if result.is_err() && self.auto_wake {
+            self.pace();
+            return self.i2c.write_read(self.address, &[start_addr], buf);
+        }
+
+        result
+    }
+
     fn read_reg(&mut self, register_idx: u8) -> Result<u8, E> {
         if register_idx >= REGISTER_COUNT {
             return Ok(0);
         }
 
         let mut register_buf = [0u8; 1];
-        self.i2c
-            .write_read(AT42QT1070_I2C_ADDR, &[register_idx], &mut register_buf)?;
+        self.write_read(register_idx, &mut register_buf)?;
+        self.sync_generation = self.sync_generation.wrapping_add(1);
+        self.trace(register_idx, Direction::Read, register_buf[0]);
+        self.mark_valid(register_idx);
+        self.synced = true;
 
         Ok(register_buf[0])
     }
 
+    // Reads the MS and LS byte of a 16 bit value in one transaction. With coherent reads the
+    // MS byte is read again and the pair re-read if it changed, at most three times. After
+    // the third changed MS byte the last pair is returned unverified.
+    fn read_ms_ls(&mut self, ms_addr: u8) -> Result<(u8, u8), E> {
+        let mut buf = [0u8; 2];
+        for _ in 0..3 {
+            self.read_reg_block(ms_addr, &mut buf)?;
+            if !self.coherent_reads || self.read_reg(ms_addr)? == buf[0] {
+                break;
+            }
+        }
+
+        Ok((buf[0], buf[1]))
+    }
+
+    fn read_reg_block(&mut self, start_addr: u8, buf: &mut [u8]) -> Result<(), E> {
+        self.write_read(start_addr, buf)?;
+        self.sync_generation = self.sync_generation.wrapping_add(1);
+        for (offset, value) in buf.iter().enumerate() {
+            let addr = start_addr.wrapping_add(offset as u8);
+            self.trace(addr, Direction::Read, *value);
+            self.mark_valid(addr);
+        }
+        self.synced = true;
+
+        Ok(())
+    }
+
+    fn mark_valid(&mut self, addr: u8) {
+        // Without the reference-data feature the references are read but not cached
+        #[cfg(not(feature = "reference-data"))]
+        if (RegisterMap::reference_ms_addr(Key::Key0)..=RegisterMap::reference_ls_addr(Key::Key6))
+            .contains(&addr)
+        {
+            return;
+        }
+
+        if let Some(valid) = self.valid.get_mut(addr as usize) {
+            *valid = true;
+        }
+    }
+
     fn read_all_reg(&mut self) -> Result<[u8; REGISTER_COUNT as usize], E> {
         let mut register_buf = [0u8; REGISTER_COUNT as usize];
-        self.i2c
-            .write_read(AT42QT1070_I2C_ADDR, &[0], &mut register_buf)?;
+        self.read_reg_block(0, &mut register_buf)?;
 
         Ok(register_buf)
     }
 
     fn write_reg_map_reg(&mut self, reg: &Register, value: u8) -> Result<(), E> {
-        match reg {
-            ChipID | FirmwareVersion | DetectionStatus | KeyStatus | KeySignalMs(_)
-            | KeySignalLs(_) | ReferenceDataMs(_) | ReferenceDataLs(_) => return Ok(()),
-            _ => {}
+        if reg.is_read_only() {
+            return Ok(());
         }
 
         self.write_reg(RegisterMap::get_register_addr(reg), value)
@@ -461,6 +1726,2005 @@ where
 
     fn write_reg(&mut self, reg_addr: u8, value: u8) -> Result<(), E> {
         let reg_buf = [reg_addr, value];
-        self.i2c.write(AT42QT1070_I2C_ADDR, &reg_buf)
+        self.pace();
+        self.i2c.write(self.address, &reg_buf)?;
+        self.trace(reg_addr, Direction::Write, value);
+
+        Ok(())
+    }
+
+    // Writes at most REGISTER_COUNT values, any further values are dropped
+    fn write_reg_block(&mut self, start_addr: u8, values: &[u8]) -> Result<(), E> {
+        let mut reg_buf = [0u8; REGISTER_COUNT as usize + 1];
+        let len = values.len().min(REGISTER_COUNT as usize);
+        reg_buf[0] = start_addr;
+        for (reg, value) in reg_buf.iter_mut().skip(1).zip(values.iter()) {
+            *reg = *value;
+        }
+        self.pace();
+        self.i2c
+            .write(self.address, reg_buf.get(..=len).unwrap_or(&reg_buf))?;
+        for (offset, value) in values.iter().take(len).enumerate() {
+            self.trace(
+                start_addr.wrapping_add(offset as u8),
+                Direction::Write,
+                *value,
+            );
+        }
+
+        Ok(())
+    }
+
+    fn pace(&self) {
+        if let Some((gap_ns, delay)) = self.transaction_gap {
+            delay(gap_ns);
+        }
+    }
+
+    fn trace(&self, register: u8, direction: Direction, value: u8) {
+        if let Some(hook) = self.trace_hook {
+            hook(TraceEvent {
+                address: self.address,
+                register,
+                direction,
+                value,
+            });
+        }
+    }
+}
+
+/// Cached touch state of a key, sync the key status first
+///
+/// ```
+/// use at42qt1070::{At42qt1070, Key};
+/// # use embedded_hal::blocking::i2c;
+/// # // Bus with Key3 touched
+/// # struct Bus;
+/// # impl i2c::Write for Bus {
+/// #     type Error = ();
+/// #     fn write(&mut self, _: u8, _: &[u8]) -> Result<(), ()> {
+/// #         Ok(())
+/// #     }
+/// # }
+/// # impl i2c::WriteRead for Bus {
+/// #     type Error = ();
+/// #     fn write_read(&mut self, _: u8, bytes: &[u8], buf: &mut [u8]) -> Result<(), ()> {
+/// #         for (addr, value) in (bytes[0]..).zip(buf.iter_mut()) {
+/// #             *value = if addr == 0x03 { 0b000_1000 } else { 0 };
+/// #         }
+/// #         Ok(())
+/// #     }
+/// # }
+/// # let i2c = Bus;
+///
+/// let mut sensor = At42qt1070::new(i2c);
+/// sensor.sync_one(&at42qt1070::Register::KeyStatus)?;
+/// assert!(sensor[Key::Key3]);
+/// assert!(!sensor[Key::Key0]);
+/// # Ok::<(), ()>(())
+/// ```
+impl<I2C> Index<Key> for At42qt1070<I2C> {
+    type Output = bool;
+
+    fn index(&self, key: Key) -> &bool {
+        &self.register_map.key_status.key[key.index()]
+    }
+}
+
+/// Forwards to a borrowed I2C struct, so the driver does not need to own the bus. embedded-hal
+/// 0.2 does not implement its I2C traits for `&mut T`, this wrapper fills the gap.
+pub struct BorrowedI2c<'a, I2C>(pub &'a mut I2C);
+
+impl<I2C: i2c::Write> i2c::Write for BorrowedI2c<'_, I2C> {
+    type Error = I2C::Error;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.0.write(address, bytes)
+    }
+}
+
+impl<I2C: i2c::WriteRead> i2c::WriteRead for BorrowedI2c<'_, I2C> {
+    type Error = I2C::Error;
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.0.write_read(address, bytes, buffer)
+    }
+}
+
+impl<'a, I2C, E> At42qt1070<BorrowedI2c<'a, I2C>>
+where
+    I2C: i2c::Write<Error = E> + i2c::WriteRead<Error = E>,
+{
+    /// Borrows `i2c` instead of taking ownership, the bus is usable again once the driver
+    /// is dropped
+    pub fn new_borrowed(i2c: &'a mut I2C) -> Self {
+        At42qt1070::new(BorrowedI2c(i2c))
+    }
+}
+
+/// The address of the device is fixed, so several sensors on one bus have to sit behind an
+/// I2C multiplexer. `select` switches the multiplexer to the channel of the given sensor index
+/// before every access, e.g. by writing 1 << index to a PCA9548A.
+pub struct MuxedSensors<'a, I2C, F> {
+    sensors: &'a mut [At42qt1070<I2C>],
+    select: F,
+}
+
+impl<'a, I2C, E, F> MuxedSensors<'a, I2C, F>
+where
+    I2C: i2c::Write<Error = E> + i2c::WriteRead<Error = E>,
+    F: FnMut(usize) -> Result<(), E>,
+{
+    pub fn new(sensors: &'a mut [At42qt1070<I2C>], select: F) -> Self {
+        Self { sensors, select }
+    }
+
+    pub fn len(&self) -> usize {
+        self.sensors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sensors.is_empty()
+    }
+
+    /// Selects the channel of sensor `index` and runs `f` on it
+    pub fn access<R>(
+        &mut self,
+        index: usize,
+        f: impl FnOnce(&mut At42qt1070<I2C>) -> Result<R, E>,
+    ) -> Result<R, Error<E>> {
+        let sensor = self.sensors.get_mut(index).ok_or(Error::InvalidArgument)?;
+        (self.select)(index).map_err(Error::I2c)?;
+
+        f(sensor).map_err(Error::I2c)
+    }
+}
+
+// Byte of register 0x35, no guard channel is encoded as 7
+fn fo_mc_guard_byte(fast_out: bool, max_cal: bool, guard_channel: Option<Key>) -> u8 {
+    let guard_channel = match guard_channel {
+        Some(key) => key as u8,
+        None => 0x07,
+    };
+
+    FastOutDiMaxCalGuardChannel {
+        fast_out,
+        max_cal,
+        guard_channel,
+    }
+    .as_byte()
+}
+
+// Decodes 14 bytes of alternating MS and LS registers into the cache, keys missing from a
+// shorter buffer keep their cached value
+fn update_u16_block(ms: &mut PerKey<u8>, ls: &mut PerKey<u8>, buf: &[u8]) -> [u16; 7] {
+    for ((ms, ls), pair) in ms.iter_mut().zip(ls.iter_mut()).zip(buf.chunks_exact(2)) {
+        *ms = pair[0];
+        *ls = pair[1];
+    }
+
+    let mut values = [0u16; 7];
+    for ((value, ms), ls) in values.iter_mut().zip(ms.iter()).zip(ls.iter()) {
+        *value = u16_from_ms_ls(*ms, *ls);
+    }
+
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::mock::{MockDelay, MockI2c, Nack, Transaction};
+
+    fn sensor() -> At42qt1070<MockI2c> {
+        At42qt1070::new(MockI2c::new())
+    }
+
+    #[test]
+    fn set_fast_out_keeps_max_cal_and_guard_channel() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().regs[0x35] = 0x13;
+        sensor.sync_one(&Register::FoMcGuard).unwrap();
+
+        sensor.set_fast_out(true).unwrap();
+        assert_eq!(sensor.i2c_mut().regs[0x35], 0x33);
+        sensor.set_fast_out(false).unwrap();
+        assert_eq!(sensor.i2c_mut().regs[0x35], 0x13);
+    }
+
+    #[test]
+    fn read_calibrate_and_reset_read_their_registers() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().regs[0x38] = 0x01;
+        sensor.i2c_mut().regs[0x39] = 0x02;
+
+        assert_eq!(sensor.read_calibrate().unwrap(), 0x01);
+        assert_eq!(sensor.read_reset().unwrap(), 0x02);
+        assert_eq!(sensor.read_cached_calibrate(), 0x01);
+        assert_eq!(sensor.read_cached_reset(), 0x02);
+        assert_eq!(
+            sensor.i2c_mut().log,
+            [
+                Transaction::Read {
+                    start: 0x38,
+                    len: 1
+                },
+                Transaction::Read {
+                    start: 0x39,
+                    len: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn poll_key_events_reports_edges_against_the_cache() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().regs[0x03] = 0b0000_0011;
+        let events: std::vec::Vec<_> = sensor.poll_key_events().unwrap().collect();
+        assert_eq!(
+            events,
+            [(Key::Key0, Edge::Pressed), (Key::Key1, Edge::Pressed)]
+        );
+
+        sensor.i2c_mut().regs[0x03] = 0b0000_0010;
+        let events: std::vec::Vec<_> = sensor.poll_key_events().unwrap().collect();
+        assert_eq!(events, [(Key::Key0, Edge::Released)]);
+        assert_eq!(sensor.poll_key_events().unwrap().count(), 0);
+    }
+
+    #[test]
+    fn continuous_acquisition_round_trips() {
+        let mut sensor = sensor();
+        sensor.set_continuous_acquisition().unwrap();
+        assert_eq!(sensor.i2c_mut().regs[0x36], 0);
+
+        sensor.sync_all().unwrap();
+        assert_eq!(
+            sensor.read_low_power_mode().unwrap(),
+            Duration::from_millis(0)
+        );
+        assert_eq!(sensor.effective_poll_interval_ms(), None);
+
+        sensor
+            .set_low_power_mode(Duration::from_millis(16))
+            .unwrap();
+        assert_eq!(
+            sensor.read_low_power_mode().unwrap(),
+            Duration::from_millis(16)
+        );
+    }
+
+    #[test]
+    fn probe_finds_the_device() {
+        let mut i2c = MockI2c::new();
+        assert_eq!(probe(&mut i2c), Ok(true));
+        assert_eq!(
+            i2c.log,
+            [Transaction::Read {
+                start: 0x00,
+                len: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn probe_reports_a_missing_device() {
+        let mut i2c = MockI2c::new();
+        i2c.present = false;
+        assert_eq!(probe(&mut i2c), Err(Nack));
+
+        // Another device answering at the address
+        let mut i2c = MockI2c::new();
+        i2c.regs[0x00] = 0x42;
+        assert_eq!(probe(&mut i2c), Ok(false));
+    }
+
+    #[cfg(feature = "reference-data")]
+    #[test]
+    fn read_signals_and_references_decodes_one_block() {
+        let mut sensor = sensor();
+        for key in 0..7u8 {
+            let key = Key::from(key);
+            sensor
+                .i2c_mut()
+                .set_u16(RegisterMap::signal_ms_addr(key), 0x0100 + key as u16);
+            sensor
+                .i2c_mut()
+                .set_u16(RegisterMap::reference_ms_addr(key), 0x0200 + key as u16);
+        }
+
+        let (signals, references) = sensor.read_signals_and_references().unwrap();
+        assert_eq!(signals, [0x100, 0x101, 0x102, 0x103, 0x104, 0x105, 0x106]);
+        assert_eq!(
+            references,
+            [0x200, 0x201, 0x202, 0x203, 0x204, 0x205, 0x206]
+        );
+        assert_eq!(sensor.read_cached_key_signal(Key::Key6), 0x106);
+        assert_eq!(sensor.read_cached_reference_data(Key::Key6), 0x206);
+        assert_eq!(
+            sensor.i2c_mut().log,
+            [Transaction::Read {
+                start: 0x04,
+                len: 28
+            }]
+        );
+    }
+
+    #[test]
+    fn is_valid_after_reading_the_register() {
+        let mut sensor = sensor();
+        assert!(!sensor.is_valid(&Register::ChipID));
+        assert!(!sensor.is_valid(&NthrKey(Key::Key2)));
+
+        sensor.read_chip_id().unwrap();
+        assert!(sensor.is_valid(&Register::ChipID));
+        assert!(!sensor.is_valid(&NthrKey(Key::Key2)));
+
+        sensor.sync_all().unwrap();
+        assert!(sensor.is_valid(&NthrKey(Key::Key2)));
+        assert!(sensor.is_valid(&Reset));
+    }
+
+    #[test]
+    fn failed_read_leaves_the_register_invalid() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().nack_next = 1;
+        assert!(sensor.read_chip_id().is_err());
+        assert!(!sensor.is_valid(&Register::ChipID));
+    }
+
+    #[test]
+    fn set_uniform_ave_keeps_the_aks_groups() {
+        let mut sensor = sensor();
+        for (key, aks) in [0u8, 1, 2, 3, 0, 1, 2].iter().enumerate() {
+            sensor.i2c_mut().regs[0x27 + key] = 8 << 2 | aks;
+        }
+        sensor.sync_all().unwrap();
+
+        sensor.set_uniform_ave(4).unwrap();
+        for (key, aks) in [0u8, 1, 2, 3, 0, 1, 2].iter().enumerate() {
+            assert_eq!(sensor.i2c_mut().regs[0x27 + key], 4 << 2 | aks);
+        }
+    }
+
+    #[test]
+    fn set_uniform_ave_rejects_invalid_ave() {
+        let mut sensor = sensor();
+        assert!(matches!(
+            sensor.set_uniform_ave(3),
+            Err(Error::InvalidArgument)
+        ));
+        assert!(sensor.i2c_mut().log.is_empty());
+    }
+
+    #[cfg(feature = "reference-data")]
+    fn set_signal_and_reference(
+        sensor: &mut At42qt1070<MockI2c>,
+        key: Key,
+        signal: u16,
+        reference: u16,
+    ) {
+        let i2c = sensor.i2c_mut();
+        i2c.set_u16(RegisterMap::signal_ms_addr(key), signal);
+        i2c.set_u16(RegisterMap::reference_ms_addr(key), reference);
+    }
+
+    #[cfg(feature = "reference-data")]
+    #[test]
+    fn strongest_touched_key_picks_the_largest_delta() {
+        let mut sensor = sensor();
+        for key in 0..7u8 {
+            set_signal_and_reference(&mut sensor, Key::from(key), 500, 500);
+        }
+        assert_eq!(sensor.strongest_touched_key().unwrap(), None);
+
+        set_signal_and_reference(&mut sensor, Key::Key1, 450, 500);
+        set_signal_and_reference(&mut sensor, Key::Key4, 420, 500);
+        // Largest delta, but not reported as touched
+        set_signal_and_reference(&mut sensor, Key::Key6, 100, 500);
+        sensor.i2c_mut().regs[0x03] = 0b001_0010;
+        assert_eq!(sensor.strongest_touched_key().unwrap(), Some(Key::Key4));
+    }
+
+    #[test]
+    fn write_image_skips_read_only_registers() {
+        let mut sensor = sensor();
+        let mut image = [0x01u8; REGISTER_COUNT as usize];
+        image[0x20] = 0x30;
+        image[0x36] = 0x05;
+
+        sensor.write_image(&image).unwrap();
+        let written: std::vec::Vec<u8> = (0x20..=0x37).collect();
+        assert_eq!(sensor.i2c_mut().written_addrs(), written);
+        assert_eq!(sensor.i2c_mut().writes().len(), 1);
+        assert_eq!(sensor.i2c_mut().regs[0x00], 0x2E);
+        assert_eq!(sensor.i2c_mut().regs[0x38], 0x00);
+        assert_eq!(sensor.read_cached_negative_threshold(Key::Key0), 0x30);
+        assert_eq!(sensor.device_reg(&Register::LowPowerMode), 0x05);
+    }
+
+    #[test]
+    fn set_ave_with_stale_cache_overwrites_the_aks() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().regs[0x29] = 8 << 2 | 3;
+
+        // The cache still holds the default AKS 1 of Key2
+        sensor.set_ave(4, Key::Key2).unwrap();
+        assert_eq!(sensor.i2c_mut().regs[0x29], 4 << 2 | 1);
+    }
+
+    #[test]
+    fn checked_setters_keep_the_other_half_of_the_device() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().regs[0x29] = 8 << 2 | 3;
+        sensor.set_ave_checked(4, Key::Key2).unwrap();
+        assert_eq!(sensor.i2c_mut().regs[0x29], 4 << 2 | 3);
+
+        sensor.i2c_mut().regs[0x2A] = 16 << 2 | 1;
+        sensor.set_aks_checked(2, Key::Key3).unwrap();
+        assert_eq!(sensor.i2c_mut().regs[0x2A], 16 << 2 | 2);
+    }
+
+    #[cfg(feature = "reference-data")]
+    #[test]
+    fn detect_open_channels_flags_out_of_range_signals() {
+        let mut sensor = sensor();
+        for key in 0..7u8 {
+            set_signal_and_reference(&mut sensor, Key::from(key), 600, 600);
+        }
+        set_signal_and_reference(&mut sensor, Key::Key1, OPEN_CHANNEL_SIGNAL_MIN - 1, 600);
+        set_signal_and_reference(&mut sensor, Key::Key2, OPEN_CHANNEL_SIGNAL_MIN, 600);
+        set_signal_and_reference(&mut sensor, Key::Key3, OPEN_CHANNEL_SIGNAL_MAX + 1, 600);
+        set_signal_and_reference(&mut sensor, Key::Key5, 600, 0);
+
+        let open = sensor.detect_open_channels().unwrap();
+        assert_eq!(open, KeyMask(0b010_1010));
+    }
+
+    #[test]
+    fn restore_state_writes_the_saved_configuration() {
+        let mut source = sensor();
+        source.i2c_mut().regs[0x22] = 0x30;
+        source.i2c_mut().regs[0x30] = 0x07;
+        source.i2c_mut().regs[0x36] = 0x09;
+        source.sync_all().unwrap();
+        let saved = source.save_state();
+
+        let mut target = sensor();
+        target.restore_state(&saved).unwrap();
+        let i2c = target.i2c_mut();
+        assert_eq!(i2c.regs[0x20..0x38], source.i2c_mut().regs[0x20..0x38]);
+        // Followed by a calibration
+        assert_eq!(i2c.regs[0x38], 0x01);
+        assert!(target.save_state() == saved);
+    }
+
+    #[test]
+    fn read_detection_only_reads_one_byte() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().regs[0x02] = 0b1100_0001;
+
+        let status = sensor.read_detection_only().unwrap();
+        assert!(status.calibrate && status.overflow && status.touch);
+        assert_eq!(
+            sensor.i2c_mut().log,
+            [Transaction::Read {
+                start: 0x02,
+                len: 1
+            }]
+        );
+
+        sensor.i2c_mut().regs[0x02] = 0;
+        let status = sensor.read_detection_only().unwrap();
+        assert!(!status.calibrate && !status.overflow && !status.touch);
+    }
+
+    #[cfg(feature = "heapless")]
+    #[test]
+    fn push_key_events_hands_events_to_the_consumer() {
+        let mut sink: EventSink<3> = EventSink::new();
+        let (mut producer, mut consumer) = sink.split();
+        let mut sensor = sensor();
+
+        sensor.i2c_mut().regs[0x03] = 0b000_0001;
+        assert_eq!(sensor.push_key_events(&mut producer).unwrap(), 0);
+        assert_eq!(consumer.dequeue(), Some((Key::Key0, Edge::Pressed)));
+        assert_eq!(consumer.dequeue(), None);
+
+        // Capacity is N - 1, two of the four edges are dropped
+        sensor.i2c_mut().regs[0x03] = 0b000_1110;
+        assert_eq!(sensor.push_key_events(&mut producer).unwrap(), 2);
+        assert_eq!(consumer.dequeue(), Some((Key::Key0, Edge::Released)));
+        assert_eq!(consumer.dequeue(), Some((Key::Key1, Edge::Pressed)));
+        assert_eq!(consumer.dequeue(), None);
+    }
+
+    #[test]
+    fn references_are_valid_only_when_cached() {
+        let mut sensor = sensor();
+        sensor.sync_all().unwrap();
+
+        assert!(sensor.is_valid(&Register::KeySignalLs(Key::Key6)));
+        assert!(sensor.is_valid(&NthrKey(Key::Key0)));
+        for key in 0..7u8 {
+            let key = Key::from(key);
+            assert_eq!(
+                sensor.is_valid(&Register::ReferenceDataMs(key)),
+                cfg!(feature = "reference-data")
+            );
+            assert_eq!(
+                sensor.is_valid(&Register::ReferenceDataLs(key)),
+                cfg!(feature = "reference-data")
+            );
+        }
+    }
+
+    #[test]
+    fn bus_sentinel_chip_ids_mean_no_device() {
+        for sentinel in [0xFF, 0x00].iter() {
+            let mut sensor = sensor();
+            sensor.i2c_mut().regs[0x00] = *sentinel;
+            assert!(matches!(
+                sensor.read_chip_id_checked(),
+                Err(Error::NoDevice)
+            ));
+            assert_eq!(probe(sensor.i2c_mut()), Ok(false));
+        }
+
+        assert_eq!(sensor().read_chip_id_checked().unwrap(), (0x2, 0xE));
+    }
+
+    #[test]
+    fn recommend_threshold_adds_the_margin_to_the_noise() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().on_read = Some(std::boxed::Box::new(|reads, regs| {
+            let signal = 500 + (reads % 4) as u16 * 3;
+            regs[0x08] = (signal >> 8) as u8;
+            regs[0x09] = signal as u8;
+        }));
+
+        assert_eq!(sensor.recommend_threshold(Key::Key2, 5).unwrap(), 9 + 5);
+        assert_eq!(sensor.i2c_mut().reads(), NOISE_SAMPLES as usize);
+    }
+
+    #[test]
+    fn read_all_negative_thresholds_reads_one_block() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().regs[0x20..0x27].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7]);
+
+        assert_eq!(
+            sensor.read_all_negative_thresholds().unwrap(),
+            [1, 2, 3, 4, 5, 6, 7]
+        );
+        assert_eq!(sensor.read_cached_negative_threshold(Key::Key6), 7);
+        assert_eq!(
+            sensor.i2c_mut().log,
+            [Transaction::Read {
+                start: 0x20,
+                len: 7
+            }]
+        );
+    }
+
+    #[test]
+    fn read_all_detection_integrators_reads_one_block() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().regs[0x2E..0x35].copy_from_slice(&[7, 6, 5, 4, 3, 2, 1]);
+
+        assert_eq!(
+            sensor.read_all_detection_integrators().unwrap(),
+            [7, 6, 5, 4, 3, 2, 1]
+        );
+        assert_eq!(sensor.read_cached_detection_integrator(Key::Key0), 7);
+        assert_eq!(
+            sensor.i2c_mut().log,
+            [Transaction::Read {
+                start: 0x2E,
+                len: 7
+            }]
+        );
+    }
+
+    #[test]
+    fn read_all_ave_aks_reads_one_block() {
+        let mut sensor = sensor();
+        for key in 0..7 {
+            sensor.i2c_mut().regs[0x27 + key] = 1 << (key % 6) << 2 | (key % 4) as u8;
+        }
+
+        let ave_aks = sensor.read_all_ave_aks().unwrap();
+        assert_eq!(
+            ave_aks,
+            [(1, 0), (2, 1), (4, 2), (8, 3), (16, 0), (32, 1), (1, 2)]
+        );
+        assert_eq!(sensor.read_cached_ave_aks(Key::Key5), (32, 1));
+        assert_eq!(
+            sensor.i2c_mut().log,
+            [Transaction::Read {
+                start: 0x27,
+                len: 7
+            }]
+        );
+    }
+
+    #[test]
+    fn reset_to_defaults_writes_the_datasheet_defaults() {
+        let mut sensor = sensor();
+        let defaults = sensor.i2c_mut().regs;
+        sensor.i2c_mut().regs[0x20..0x38].copy_from_slice(&[0x11; 24]);
+        sensor.sync_all().unwrap();
+
+        sensor.reset_to_defaults().unwrap();
+        assert_eq!(sensor.i2c_mut().regs, defaults);
+        assert_eq!(
+            sensor.i2c_mut().written_addrs().len(),
+            CONFIG_REGISTER_COUNT
+        );
+        assert!(sensor.save_state() == DeviceConfig::default());
+    }
+
+    #[test]
+    fn change_line_clears_only_after_reading_the_status() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().change = true;
+
+        sensor.read_chip_id().unwrap();
+        sensor.read_key_signal(Key::Key0).unwrap();
+        sensor.sync_one(&NthrKey(Key::Key0)).unwrap();
+        assert!(sensor.i2c_mut().change);
+
+        sensor.clear_change_line().unwrap();
+        assert!(!sensor.i2c_mut().change);
+        assert_eq!(
+            sensor.i2c_mut().log.last(),
+            Some(&Transaction::Read {
+                start: 0x02,
+                len: 2
+            })
+        );
+    }
+
+    #[test]
+    fn auto_wake_retries_a_nacked_read_once() {
+        let mut sensor = sensor();
+        sensor.set_auto_wake(true);
+        sensor.i2c_mut().nack_next = 1;
+        assert_eq!(sensor.read_chip_id().unwrap(), (0x2, 0xE));
+        assert_eq!(sensor.i2c_mut().nacks, 1);
+
+        // Only a single retry
+        sensor.i2c_mut().nack_next = 2;
+        assert_eq!(sensor.read_chip_id(), Err(Nack));
+        assert_eq!(sensor.i2c_mut().nacks, 3);
+    }
+
+    #[test]
+    fn without_auto_wake_a_nacked_read_fails() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().nack_next = 1;
+        assert_eq!(sensor.read_chip_id(), Err(Nack));
+        assert_eq!(sensor.i2c_mut().nacks, 1);
+        assert_eq!(sensor.read_chip_id().unwrap(), (0x2, 0xE));
+    }
+
+    #[test]
+    fn verify_identity_reports_a_different_chip_id() {
+        let mut sensor = sensor();
+        assert!(sensor.verify_identity().is_ok());
+
+        sensor.i2c_mut().regs[0x00] = 0x3A;
+        assert!(matches!(
+            sensor.verify_identity(),
+            Err(Error::IdentityMismatch {
+                expected: 0x2E,
+                actual: 0x3A
+            })
+        ));
+    }
+
+    #[test]
+    fn effective_poll_interval_follows_the_low_power_mode() {
+        let mut sensor = sensor();
+        assert_eq!(sensor.effective_poll_interval_ms(), Some(16));
+
+        sensor.i2c_mut().regs[0x36] = 0;
+        sensor.read_low_power_mode().unwrap();
+        assert_eq!(sensor.effective_poll_interval_ms(), None);
+
+        sensor.i2c_mut().regs[0x36] = 255;
+        sensor.read_low_power_mode().unwrap();
+        assert_eq!(sensor.effective_poll_interval_ms(), Some(2040));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn write_record_writes_one_csv_line() {
+        let frame = Frame {
+            status: DetectionStatus {
+                calibrate: false,
+                overflow: true,
+                touch: true,
+            },
+            keys: KeyMask(0b000_0101),
+            signals: [100, 101, 102, 103, 104, 105, 106],
+            #[cfg(feature = "reference-data")]
+            references: [200, 201, 202, 203, 204, 205, 206],
+        };
+
+        let mut line = std::string::String::new();
+        frame.write_record(&mut line).unwrap();
+        #[cfg(feature = "reference-data")]
+        assert_eq!(
+            line,
+            "0,1,1,5,100,101,102,103,104,105,106,200,201,202,203,204,205,206\n"
+        );
+        #[cfg(not(feature = "reference-data"))]
+        assert_eq!(line, "0,1,1,5,100,101,102,103,104,105,106\n");
+    }
+
+    #[test]
+    fn set_max_cal_keeps_fast_out_and_the_guard_channel() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().regs[0x35] = 0x25;
+        sensor.sync_one(&Register::FoMcGuard).unwrap();
+
+        sensor.set_max_cal(true).unwrap();
+        assert_eq!(sensor.i2c_mut().regs[0x35], 0x35);
+        sensor.set_max_cal(false).unwrap();
+        assert_eq!(sensor.i2c_mut().regs[0x35], 0x25);
+    }
+
+    #[test]
+    fn read_all_key_signals_fails_on_overflow() {
+        let mut sensor = sensor();
+        sensor
+            .i2c_mut()
+            .set_u16(RegisterMap::signal_ms_addr(Key::Key3), 0x0123);
+        assert_eq!(sensor.read_all_key_signals().unwrap()[3], 0x0123);
+
+        sensor.i2c_mut().regs[0x02] = 1 << 6;
+        assert!(matches!(
+            sensor.read_all_key_signals(),
+            Err(SignalError::Overflow)
+        ));
+        // The unchecked variant still returns the signals
+        assert_eq!(sensor.read_all_key_signals_unchecked().unwrap()[3], 0x0123);
+        assert_eq!(
+            sensor.i2c_mut().log.last(),
+            Some(&Transaction::Read {
+                start: 0x02,
+                len: 16
+            })
+        );
+    }
+
+    #[test]
+    fn calibrate_checked_reports_a_held_key() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().on_read = Some(std::boxed::Box::new(|reads, regs| {
+            regs[0x02] = if reads < 3 { 0x80 } else { 0x01 };
+            regs[0x03] = 0b000_0100;
+        }));
+
+        let mut delay = MockDelay::default();
+        let result = sensor.calibrate_checked(&mut delay, 100).unwrap();
+        assert_eq!(
+            result,
+            CalibrationResult::TouchedDuringCalibration(KeyMask::from(Key::Key2))
+        );
+        assert_eq!(delay.ms, 4);
+        assert_eq!(sensor.i2c_mut().regs[0x38], 0x01);
+    }
+
+    #[test]
+    fn calibrate_checked_times_out() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().regs[0x02] = 0x80;
+
+        let mut delay = MockDelay::default();
+        let result = sensor.calibrate_checked(&mut delay, 10).unwrap();
+        assert_eq!(result, CalibrationResult::TimedOut);
+        assert_eq!(delay.ms, 10);
+
+        sensor.i2c_mut().regs[0x02] = 0x00;
+        let result = sensor.calibrate_checked(&mut delay, 10).unwrap();
+        assert_eq!(result, CalibrationResult::Completed);
+    }
+
+    #[test]
+    fn set_aks_group_members_warns_about_small_groups() {
+        let mut sensor = sensor();
+        sensor.sync_all().unwrap();
+
+        let warning = sensor.set_aks_group_members(AksGroup::Group2, &[Key::Key3]);
+        assert_eq!(
+            warning,
+            Ok(Some(ConfigWarning::GroupTooSmall(AksGroup::Group2)))
+        );
+        assert_eq!(sensor.read_cached_ave_aks(Key::Key3).1, 2);
+
+        let warning = sensor.set_aks_group_members(AksGroup::Group1, &[Key::Key1, Key::Key2]);
+        assert_eq!(warning, Ok(None));
+        let aks: std::vec::Vec<u8> = (0..7)
+            .map(|key| sensor.i2c_mut().regs[0x27 + key] & 0x03)
+            .collect();
+        assert_eq!(aks, [0, 1, 1, 2, 0, 0, 0]);
+
+        let warning = sensor.set_aks_group_members(AksGroup::Group1, &[]);
+        assert_eq!(
+            warning,
+            Ok(Some(ConfigWarning::GroupTooSmall(AksGroup::Group1)))
+        );
+    }
+
+    #[test]
+    fn acquisition_state_mapping() {
+        let status = |byte: u8| {
+            let mut status = DetectionStatus::default();
+            status.update(byte);
+            status
+        };
+        let keys = KeyMask::from(Key::Key1);
+
+        let state = AcquisitionState::from_status;
+        assert_eq!(state(&status(0xC1), keys), AcquisitionState::Calibrating);
+        assert_eq!(state(&status(0x41), keys), AcquisitionState::Overflow);
+        assert_eq!(state(&status(0x01), keys), AcquisitionState::Touch(keys));
+        assert_eq!(state(&status(0x00), KeyMask(0)), AcquisitionState::Idle);
+    }
+
+    #[test]
+    fn acquisition_state_reads_both_status_registers() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().regs[0x02] = 0x01;
+        sensor.i2c_mut().regs[0x03] = 0b010_0000;
+
+        assert_eq!(
+            sensor.acquisition_state().unwrap(),
+            AcquisitionState::Touch(KeyMask::from(Key::Key5))
+        );
+        assert_eq!(
+            sensor.i2c_mut().log,
+            [Transaction::Read {
+                start: 0x02,
+                len: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn read_key_signals_for_coalesces_contiguous_keys() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().set_u16(0x06, 0x0102);
+        sensor.i2c_mut().set_u16(0x08, 0x0304);
+        sensor.i2c_mut().set_u16(0x0A, 0x0506);
+
+        let mut signals = [0u16; 3];
+        let len = sensor
+            .read_key_signals_for(&[Key::Key1, Key::Key2, Key::Key3], &mut signals)
+            .unwrap();
+        assert_eq!(len, 3);
+        assert_eq!(signals, [0x0102, 0x0304, 0x0506]);
+        assert_eq!(
+            sensor.i2c_mut().log,
+            [Transaction::Read {
+                start: 0x06,
+                len: 6
+            }]
+        );
+    }
+
+    #[test]
+    fn read_key_signals_for_reads_scattered_keys_one_by_one() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().set_u16(0x04, 0x0A0B);
+        sensor.i2c_mut().set_u16(0x10, 0x0C0D);
+
+        let mut signals = [0u16; 4];
+        let len = sensor
+            .read_key_signals_for(&[Key::Key6, Key::Key0], &mut signals)
+            .unwrap();
+        assert_eq!(len, 2);
+        assert_eq!(signals, [0x0C0D, 0x0A0B, 0, 0]);
+        assert_eq!(sensor.i2c_mut().reads(), 2);
+        assert_eq!(sensor.read_cached_key_signal(Key::Key6), 0x0C0D);
+        assert_eq!(sensor.read_cached_key_signal(Key::Key0), 0x0A0B);
+    }
+
+    #[test]
+    fn driver_is_send_and_sync_when_the_bus_is() {
+        struct Bus;
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<At42qt1070<Bus>>();
+    }
+
+    #[test]
+    fn set_guard_channel_keeps_fast_out_and_max_cal() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().regs[0x2A] = 0x20;
+        sensor.i2c_mut().regs[0x35] = 0x30;
+        sensor.sync_one(&Register::AveAksKey(Key::Key3)).unwrap();
+        sensor.sync_one(&Register::FoMcGuard).unwrap();
+
+        sensor.set_guard_channel(Some(Key::Key3)).unwrap();
+        assert_eq!(sensor.i2c_mut().regs[0x35], 0x33);
+        sensor.set_guard_channel(None).unwrap();
+        assert_eq!(sensor.i2c_mut().regs[0x35], 0x37);
+    }
+
+    #[cfg(feature = "reference-data")]
+    #[test]
+    fn calibration_quality_is_the_reference_spread() {
+        let mut sensor = sensor();
+        for (key, reference) in [500, 502, 498, 501, 499, 500, 503].iter().enumerate() {
+            set_signal_and_reference(&mut sensor, Key::from(key as u8), 0, *reference);
+        }
+        assert_eq!(sensor.calibration_quality().unwrap(), 5);
+
+        set_signal_and_reference(&mut sensor, Key::Key4, 0, 320);
+        assert_eq!(sensor.calibration_quality().unwrap(), 183);
+    }
+
+    #[test]
+    fn wait_for_release_returns_once_the_key_is_released() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().regs[0x03] = 0b0000_0100;
+        sensor.i2c_mut().on_read = Some(std::boxed::Box::new(|reads, regs| {
+            if reads == 3 {
+                regs[0x03] = 0;
+            }
+        }));
+
+        sensor.wait_for_release_blocking(Key::Key2).unwrap();
+        assert_eq!(sensor.i2c_mut().reads(), 4);
+
+        sensor.i2c_mut().regs[0x03] = 0b0000_0100;
+        sensor.i2c_mut().log.clear();
+        sensor.i2c_mut().on_read = Some(std::boxed::Box::new(|reads, regs| {
+            if reads == 6 {
+                regs[0x03] = 0;
+            }
+        }));
+        let mut delay = MockDelay::default();
+        assert!(sensor
+            .wait_for_release_timeout(Key::Key2, &mut delay, 10)
+            .unwrap());
+        assert_eq!(delay.ms, 2);
+    }
+
+    #[test]
+    fn wait_for_release_timeout_gives_up_on_a_held_key() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().regs[0x03] = 0b0000_0100;
+
+        let mut delay = MockDelay::default();
+        assert!(!sensor
+            .wait_for_release_timeout(Key::Key2, &mut delay, 5)
+            .unwrap());
+        assert_eq!(delay.ms, 5);
+        assert_eq!(sensor.i2c_mut().reads(), 6);
+    }
+
+    #[test]
+    fn i2c_mut_transactions_bypass_the_cache() {
+        use embedded_hal::blocking::i2c::Write;
+
+        let mut sensor = sensor();
+        sensor.i2c_mut().write(0x1B, &[0x20, 42]).unwrap();
+        assert_eq!(sensor.i2c_mut().write(0x50, &[0x00, 1]), Err(Nack));
+
+        assert_ne!(sensor.device_reg(&NthrKey(Key::Key0)), 42);
+        sensor.sync_one(&NthrKey(Key::Key0)).unwrap();
+        assert_eq!(sensor.device_reg(&NthrKey(Key::Key0)), 42);
+    }
+
+    #[test]
+    fn write_register_rejects_read_only_registers() {
+        let mut sensor = sensor();
+        let reg = Register::KeySignalMs(Key::Key0);
+
+        assert!(matches!(
+            sensor.write_register(&reg, 0x12),
+            Err(Error::ReadOnly(Register::KeySignalMs(Key::Key0)))
+        ));
+        assert!(sensor.i2c_mut().log.is_empty());
+
+        sensor.write_register(&NthrKey(Key::Key0), 30).unwrap();
+        assert_eq!(sensor.i2c_mut().writes(), [(0x20, std::vec![30])]);
+        assert_eq!(sensor.device_reg(&NthrKey(Key::Key0)), 30);
+    }
+
+    #[test]
+    fn set_poll_frequency_rounds_to_8_ms_steps() {
+        let mut sensor = sensor();
+
+        assert_eq!(sensor.set_poll_frequency(60).unwrap(), 62500);
+        assert_eq!(sensor.i2c_mut().regs[0x36], 2);
+        assert_eq!(sensor.set_poll_frequency(125).unwrap(), 125_000);
+        assert_eq!(sensor.i2c_mut().regs[0x36], 1);
+        assert_eq!(sensor.set_poll_frequency(1).unwrap(), 1000);
+        assert_eq!(sensor.i2c_mut().regs[0x36], 125);
+    }
+
+    #[test]
+    fn set_poll_frequency_rejects_unrepresentable_frequencies() {
+        let mut sensor = sensor();
+
+        assert!(matches!(
+            sensor.set_poll_frequency(0),
+            Err(Error::InvalidArgument)
+        ));
+        assert!(matches!(
+            sensor.set_poll_frequency(126),
+            Err(Error::InvalidArgument)
+        ));
+        assert!(sensor.i2c_mut().log.is_empty());
+    }
+
+    #[cfg(feature = "reference-data")]
+    #[test]
+    fn multitouch_reports_keys_suppressed_by_their_group() {
+        let mut sensor = sensor();
+        for key in 0..7 {
+            set_signal_and_reference(&mut sensor, Key::from(key), 600, 600);
+        }
+        // Key1 and Key2 share AKS group 1, only Key1 reports as touched
+        sensor.i2c_mut().regs[0x03] = 0b0000_0010;
+        set_signal_and_reference(&mut sensor, Key::Key1, 540, 600);
+        set_signal_and_reference(&mut sensor, Key::Key2, 550, 600);
+        set_signal_and_reference(&mut sensor, Key::Key5, 590, 600);
+
+        let touched = sensor.raw_signals_indicate_multitouch(20).unwrap();
+        assert_eq!(touched, KeyMask::from(Key::Key1) | KeyMask::from(Key::Key2));
+        assert_eq!(
+            sensor.read_full_key_status().unwrap(),
+            [false, true, false, false, false, false, false]
+        );
+    }
+
+    // Text search backing the README claim that the library code makes no explicit panic
+    // calls. Implicit panics, e.g. from indexing, are not detected. Comments and doc examples
+    // are skipped.
+    #[test]
+    fn library_code_has_no_explicit_panics() {
+        let sources = [
+            ("lib.rs", include_str!("lib.rs")),
+            ("config.rs", include_str!("config.rs")),
+            ("device.rs", include_str!("device.rs")),
+            ("error.rs", include_str!("error.rs")),
+            ("helpers.rs", include_str!("helpers.rs")),
+            ("register_map.rs", include_str!("register_map.rs")),
+        ];
+        let forbidden = [
+            ".unwrap()",
+            ".expect(",
+            "panic!(",
+            "unreachable!(",
+            "todo!(",
+            "unimplemented!(",
+            "assert!(",
+            "assert_eq!(",
+            "assert_ne!(",
+        ];
+
+        for (file, source) in sources.iter() {
+            let code = source.split("\nmod tests {").next().unwrap_or_default();
+            for (line, text) in code.lines().enumerate() {
+                if text.trim_start().starts_with("//") {
+                    continue;
+                }
+                for pattern in forbidden.iter() {
+                    assert!(!text.contains(pattern), "{}:{}: {}", file, line + 1, text);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn update_u16_block_ignores_missing_bytes() {
+        let mut ms = PerKey([1u8; 7]);
+        let mut ls = PerKey([2u8; 7]);
+
+        let values = update_u16_block(&mut ms, &mut ls, &[0x03, 0x04, 0x05]);
+        assert_eq!(values[0], 0x0304);
+        assert_eq!(values[1..], [0x0102; 6]);
+    }
+
+    #[test]
+    fn read_key_signal_reads_ms_and_ls_in_one_transaction() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().set_u16(0x0C, 0x02A7);
+
+        assert_eq!(sensor.read_key_signal(Key::Key4).unwrap(), 0x02A7);
+        assert_eq!(
+            sensor.i2c_mut().log,
+            [Transaction::Read {
+                start: 0x0C,
+                len: 2
+            }]
+        );
+        assert_eq!(sensor.device_reg(&Register::KeySignalMs(Key::Key4)), 0x02);
+        assert_eq!(sensor.device_reg(&Register::KeySignalLs(Key::Key4)), 0xA7);
+    }
+
+    #[cfg(feature = "reference-data")]
+    #[test]
+    fn read_reference_data_reads_ms_and_ls_in_one_transaction() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().set_u16(0x1C, 0x0312);
+
+        assert_eq!(sensor.read_reference_data(Key::Key5).unwrap(), 0x0312);
+        assert_eq!(
+            sensor.i2c_mut().log,
+            [Transaction::Read {
+                start: 0x1C,
+                len: 2
+            }]
+        );
+        assert_eq!(sensor.read_cached_reference_data(Key::Key5), 0x0312);
+    }
+
+    #[test]
+    fn muxed_sensors_select_the_channel_before_each_access() {
+        use core::cell::RefCell;
+
+        let order = RefCell::new(std::vec::Vec::new());
+        let mut sensors = [sensor(), sensor()];
+        sensors[1].i2c_mut().regs[0x03] = 0b0000_0001;
+        let mut muxed = MuxedSensors::new(&mut sensors, |channel| {
+            order.borrow_mut().push(("select", channel));
+            Ok(())
+        });
+
+        let status = muxed
+            .access(1, |sensor| {
+                order.borrow_mut().push(("access", 1));
+                sensor.read_key_status(Key::Key0)
+            })
+            .unwrap();
+        assert!(status);
+        assert!(!muxed
+            .access(0, |sensor| sensor.read_key_status(Key::Key0))
+            .unwrap());
+        assert_eq!(
+            *order.borrow(),
+            [("select", 1), ("access", 1), ("select", 0)]
+        );
+    }
+
+    #[test]
+    fn muxed_sensors_reject_an_out_of_range_index() {
+        let mut selected = false;
+        let mut sensors = [sensor()];
+        let mut muxed = MuxedSensors::new(&mut sensors, |_| {
+            selected = true;
+            Ok(())
+        });
+
+        assert!(matches!(
+            muxed.access(1, |sensor| sensor.read_key_status(Key::Key0)),
+            Err(Error::InvalidArgument)
+        ));
+        assert!(!selected);
+    }
+
+    #[test]
+    fn muxed_sensors_forward_select_errors() {
+        let mut sensors = [sensor()];
+        let mut muxed = MuxedSensors::new(&mut sensors, |_| Err(Nack));
+
+        assert!(matches!(
+            muxed.access(0, |sensor| sensor.read_key_status(Key::Key0)),
+            Err(Error::I2c(Nack))
+        ));
+    }
+
+    #[test]
+    fn key_status_byte_accessors() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().regs[0x03] = 0b0100_1010;
+
+        assert_eq!(sensor.read_cached_key_status_byte(), 0);
+        assert_eq!(sensor.read_key_status_byte().unwrap(), 0b0100_1010);
+        assert_eq!(sensor.read_cached_key_status_byte(), 0b0100_1010);
+        assert_eq!(
+            sensor.i2c_mut().log,
+            [Transaction::Read {
+                start: 0x03,
+                len: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn sleep_and_wake_restore_the_previous_interval() {
+        let mut sensor = sensor();
+        sensor
+            .set_low_power_mode(Duration::from_millis(40))
+            .unwrap();
+
+        sensor.sleep().unwrap();
+        assert_eq!(sensor.i2c_mut().regs[0x36], 0xFF);
+        // A second sleep must not overwrite the saved interval with 255
+        sensor.sleep().unwrap();
+        sensor.wake_to(None).unwrap();
+        assert_eq!(sensor.i2c_mut().regs[0x36], 5);
+        assert_eq!(sensor.device_reg(&Register::LowPowerMode), 5);
+
+        // Without a prior sleep the datasheet default is restored
+        sensor.wake_to(None).unwrap();
+        assert_eq!(sensor.i2c_mut().regs[0x36], 2);
+    }
+
+    #[test]
+    fn wake_to_an_explicit_interval() {
+        let mut sensor = sensor();
+        sensor.sleep().unwrap();
+
+        sensor.wake_to(Some(Duration::from_millis(64))).unwrap();
+        assert_eq!(sensor.i2c_mut().regs[0x36], 8);
+        assert_eq!(
+            sensor.i2c_mut().written_addrs(),
+            [0x36, 0x36],
+            "only the low power mode register is written"
+        );
+    }
+
+    #[cfg(feature = "reference-data")]
+    #[test]
+    fn calibrate_and_refresh_reads_the_new_references() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().on_read = Some(std::boxed::Box::new(|reads, regs| {
+            regs[0x02] = if reads < 2 { 0x80 } else { 0x00 };
+            regs[0x12] = 0x02;
+            regs[0x13] = 0x58;
+        }));
+
+        let mut delay = MockDelay::default();
+        let result = sensor.calibrate_and_refresh(&mut delay, 50).unwrap();
+        assert_eq!(result, CalibrationResult::Completed);
+        assert_eq!(sensor.read_cached_reference_data(Key::Key0), 600);
+    }
+
+    #[cfg(feature = "reference-data")]
+    #[test]
+    fn calibrate_and_refresh_gives_up_after_the_timeout() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().regs[0x02] = 0x80;
+        sensor.i2c_mut().set_u16(0x12, 600);
+
+        let mut delay = MockDelay::default();
+        let result = sensor.calibrate_and_refresh(&mut delay, 20).unwrap();
+        assert_eq!(result, CalibrationResult::TimedOut);
+        assert_eq!(delay.ms, 20);
+        assert_eq!(sensor.read_cached_reference_data(Key::Key0), 0);
+    }
+
+    #[cfg(feature = "reference-data")]
+    #[test]
+    fn signal_ratio_in_per_mille() {
+        let mut sensor = sensor();
+        set_signal_and_reference(&mut sensor, Key::Key3, 450, 600);
+        assert_eq!(sensor.signal_ratio(Key::Key3).unwrap(), 750);
+
+        set_signal_and_reference(&mut sensor, Key::Key3, 0xFFFF, 1);
+        assert_eq!(sensor.signal_ratio(Key::Key3).unwrap(), u16::MAX);
+    }
+
+    #[cfg(feature = "reference-data")]
+    #[test]
+    fn signal_ratio_is_zero_without_a_reference() {
+        let mut sensor = sensor();
+        set_signal_and_reference(&mut sensor, Key::Key3, 450, 0);
+
+        assert_eq!(sensor.signal_ratio(Key::Key3).unwrap(), 0);
+    }
+
+    #[test]
+    fn has_synced_after_the_first_successful_read() {
+        let mut sensor = sensor();
+        assert!(!sensor.has_synced());
+
+        sensor.i2c_mut().nack_next = 1;
+        assert!(sensor.sync_one(&Register::KeyStatus).is_err());
+        assert!(!sensor.has_synced());
+
+        sensor.sync_one(&Register::KeyStatus).unwrap();
+        assert!(sensor.has_synced());
+
+        let mut fresh = At42qt1070::new(MockI2c::new());
+        fresh.sync_all().unwrap();
+        assert!(fresh.has_synced());
+    }
+
+    #[test]
+    fn set_all_key_configs_writes_three_blocks() {
+        let mut sensor = sensor();
+        let mut configs = [KeyConfig {
+            nthr: 25,
+            ave_aks: AveAks { ave: 16, aks: 2 },
+            di: 3,
+        }; 7];
+        configs[6].nthr = 40;
+
+        sensor.set_all_key_configs(&configs).unwrap();
+        assert_eq!(
+            sensor.i2c_mut().writes(),
+            [
+                (0x20, std::vec![25, 25, 25, 25, 25, 25, 40]),
+                (0x27, std::vec![0x42; 7]),
+                (0x2E, std::vec![3; 7]),
+            ]
+        );
+        assert_eq!(sensor.read_cached_negative_threshold(Key::Key6), 40);
+        assert_eq!(sensor.read_cached_ave_aks(Key::Key2), (16, 2));
+        let cached = DeviceConfig::from(&sensor.register_map);
+        sensor.verify_config(&cached).unwrap();
+    }
+
+    #[test]
+    fn set_all_key_configs_writes_nothing_for_an_invalid_config() {
+        let mut sensor = sensor();
+        let mut configs = [KeyConfig {
+            nthr: 25,
+            ave_aks: AveAks::default(),
+            di: 3,
+        }; 7];
+        configs[4].ave_aks.ave = 3;
+
+        assert!(matches!(
+            sensor.set_all_key_configs(&configs),
+            Err(Error::InvalidArgument)
+        ));
+        assert!(sensor.i2c_mut().log.is_empty());
+        assert_eq!(sensor.read_cached_negative_threshold(Key::Key0), 33);
+    }
+
+    #[test]
+    fn clear_change_line_monitored_fails_on_a_stuck_line() {
+        let mut sensor = sensor();
+        let mut monitor = ChangeLineMonitor::new(2);
+
+        assert!(sensor
+            .clear_change_line_monitored(&mut monitor, || true)
+            .is_ok());
+        assert!(matches!(
+            sensor.clear_change_line_monitored(&mut monitor, || true),
+            Err(Error::ChangeLineStuck)
+        ));
+        assert_eq!(sensor.i2c_mut().reads(), 2);
+    }
+
+    #[test]
+    fn read_all_returns_a_snapshot_of_the_register_image() {
+        let mut sensor = sensor();
+        let i2c = sensor.i2c_mut();
+        i2c.regs[0x02] = 0x01;
+        i2c.regs[0x03] = 0b000_0101;
+        i2c.set_u16(0x04, 0x0123);
+        i2c.set_u16(0x10, 0x0345);
+        i2c.set_u16(0x1E, 0x0367);
+        i2c.regs[0x20] = 42;
+        i2c.regs[0x2D] = 0x42;
+        i2c.regs[0x34] = 6;
+        i2c.regs[0x35] = 0x23;
+        i2c.regs[0x36] = 7;
+        i2c.regs[0x37] = 99;
+        let image = i2c.regs;
+
+        let snapshot = sensor.read_all().unwrap();
+        assert_eq!(
+            sensor.i2c_mut().log,
+            [Transaction::Read {
+                start: 0x00,
+                len: REGISTER_COUNT as usize
+            }]
+        );
+        for addr in 0..REGISTER_COUNT {
+            if let Some(reg) = Register::from_addr(addr) {
+                #[cfg(not(feature = "reference-data"))]
+                if matches!(
+                    reg,
+                    Register::ReferenceDataMs(_) | Register::ReferenceDataLs(_)
+                ) {
+                    continue;
+                }
+                assert_eq!(
+                    snapshot.reg_as_byte(&reg),
+                    image[addr as usize],
+                    "{:?}",
+                    reg
+                );
+            }
+        }
+
+        // The snapshot is a copy, later reads do not change it
+        sensor.i2c_mut().regs[0x20] = 43;
+        sensor.sync_all().unwrap();
+        assert_eq!(snapshot.reg_as_byte(&NthrKey(Key::Key0)), 42);
+    }
+
+    #[test]
+    fn coherent_reads_retry_a_torn_value() {
+        let mut sensor = sensor();
+        sensor.set_coherent_reads(true);
+        sensor.i2c_mut().set_u16(0x04, 0x01FF);
+        // The device updates the signal to 0x0200 right after the first pair was read
+        sensor.i2c_mut().on_read = Some(std::boxed::Box::new(|reads, regs| {
+            if reads == 1 {
+                regs[0x04] = 0x02;
+                regs[0x05] = 0x00;
+            }
+        }));
+
+        assert_eq!(sensor.read_key_signal(Key::Key0).unwrap(), 0x0200);
+        let read = |start, len| Transaction::Read { start, len };
+        assert_eq!(
+            sensor.i2c_mut().log,
+            [read(0x04, 2), read(0x04, 1), read(0x04, 2), read(0x04, 1)]
+        );
+    }
+
+    #[test]
+    fn coherent_reads_give_up_after_three_attempts() {
+        let mut sensor = sensor();
+        sensor.set_coherent_reads(true);
+        sensor.i2c_mut().on_read = Some(std::boxed::Box::new(|reads, regs| {
+            regs[0x04] = reads as u8;
+            regs[0x05] = 0x80;
+        }));
+
+        assert_eq!(sensor.read_key_signal(Key::Key0).unwrap(), 0x0480);
+        assert_eq!(sensor.i2c_mut().reads(), 6);
+    }
+
+    #[test]
+    fn reads_without_coherence_take_one_transaction() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().on_read = Some(std::boxed::Box::new(|reads, regs| {
+            regs[0x04] = reads as u8;
+        }));
+
+        sensor.read_key_signal(Key::Key0).unwrap();
+        assert_eq!(sensor.i2c_mut().reads(), 1);
+    }
+
+    #[test]
+    fn delta_from_baseline_is_relative_to_the_captured_signals() {
+        let mut sensor = sensor();
+        for key in 0..7u8 {
+            sensor.i2c_mut().set_u16(
+                RegisterMap::signal_ms_addr(Key::from(key)),
+                500 + key as u16,
+            );
+        }
+        sensor.capture_baseline().unwrap();
+
+        sensor.i2c_mut().set_u16(0x04, 470);
+        sensor.i2c_mut().set_u16(0x0E, 520);
+        assert_eq!(
+            sensor.delta_from_baseline().unwrap(),
+            [-30, 0, 0, 0, 0, 15, 0]
+        );
+
+        // Capturing again moves the baseline to the current signals
+        sensor.capture_baseline().unwrap();
+        assert_eq!(sensor.delta_from_baseline().unwrap(), [0; 7]);
+    }
+
+    #[test]
+    fn calibrate_poll_finishes_within_the_cap() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().on_read = Some(std::boxed::Box::new(|reads, regs| {
+            regs[0x02] = if reads < 4 { 0x80 } else { 0x00 };
+        }));
+
+        assert!(sensor.calibrate_poll(5).unwrap());
+        assert_eq!(sensor.i2c_mut().reads(), 5);
+    }
+
+    #[test]
+    fn calibrate_poll_gives_up_at_the_cap() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().regs[0x02] = 0x80;
+
+        assert!(!sensor.calibrate_poll(5).unwrap());
+        assert_eq!(sensor.i2c_mut().reads(), 5);
+        assert!(!sensor.calibrate_poll(0).unwrap());
+        assert_eq!(sensor.i2c_mut().reads(), 5);
+    }
+
+    #[cfg(feature = "reference-data")]
+    #[test]
+    fn margins_turn_negative_for_a_triggered_channel() {
+        let mut sensor = sensor();
+        for key in 0..7 {
+            set_signal_and_reference(&mut sensor, Key::from(key), 600, 600);
+        }
+        set_signal_and_reference(&mut sensor, Key::Key0, 590, 600);
+        set_signal_and_reference(&mut sensor, Key::Key2, 570, 600);
+        set_signal_and_reference(&mut sensor, Key::Key5, 610, 600);
+
+        assert_eq!(sensor.margins().unwrap(), [23, 20, -10, 20, 20, 30, 20]);
+    }
+
+    #[test]
+    fn sync_range_decodes_registers_of_every_type() {
+        let mut sensor = sensor();
+        let i2c = sensor.i2c_mut();
+        i2c.regs[0x03] = 0b000_0010;
+        i2c.set_u16(0x04, 0x0234);
+        i2c.regs[0x34] = 6;
+        i2c.regs[0x35] = 0x21;
+        i2c.regs[0x36] = 9;
+        i2c.regs[0x37] = 50;
+
+        sensor.sync_range(0x03, 0x05).unwrap();
+        assert!(sensor[Key::Key1]);
+        assert_eq!(sensor.read_cached_key_signal(Key::Key0), 0x0234);
+
+        sensor.sync_range(0x34, 0x37).unwrap();
+        assert_eq!(sensor.device_reg(&DIKey(Key::Key6)), 6);
+        assert_eq!(sensor.device_reg(&FoMcGuard), 0x21);
+        assert_eq!(sensor.device_reg(&Register::LowPowerMode), 9);
+        assert_eq!(sensor.device_reg(&Register::MaxOnDuration), 50);
+
+        let read = |start, len| Transaction::Read { start, len };
+        assert_eq!(sensor.i2c_mut().log, [read(0x03, 3), read(0x34, 4)]);
+    }
+
+    #[test]
+    fn sync_range_clamps_the_end_and_skips_empty_ranges() {
+        let mut sensor = sensor();
+
+        sensor.sync_range(0x38, 0xFF).unwrap();
+        sensor.sync_range(0x10, 0x0F).unwrap();
+        assert_eq!(
+            sensor.i2c_mut().log,
+            [Transaction::Read {
+                start: 0x38,
+                len: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn transaction_gap_delays_every_transaction() {
+        use core::sync::atomic::{AtomicU32, Ordering};
+
+        static WAITED_NS: AtomicU32 = AtomicU32::new(0);
+        fn delay(ns: u32) {
+            WAITED_NS.fetch_add(ns, Ordering::Relaxed);
+        }
+
+        let mut sensor = sensor();
+        sensor.set_transaction_gap_ns(500, delay);
+        sensor.read_key_signal(Key::Key0).unwrap();
+        sensor.set_negative_threshold(30, Key::Key0).unwrap();
+        sensor.read_all_negative_thresholds().unwrap();
+        assert_eq!(WAITED_NS.load(Ordering::Relaxed), 1500);
+
+        sensor.set_transaction_gap_ns(0, delay);
+        sensor.read_key_signal(Key::Key0).unwrap();
+        sensor.set_negative_threshold(31, Key::Key0).unwrap();
+        assert_eq!(WAITED_NS.load(Ordering::Relaxed), 1500);
+    }
+
+    #[cfg(feature = "reference-data")]
+    #[test]
+    fn recover_if_miscalibrated_recalibrates_a_corrupted_baseline() {
+        let mut sensor = sensor();
+        for key in 0..7 {
+            set_signal_and_reference(&mut sensor, Key::from(key), 600, 600);
+        }
+        assert!(!sensor.recover_if_miscalibrated().unwrap());
+        assert_eq!(sensor.i2c_mut().regs[0x38], 0);
+
+        // Key3 was touched during calibration, its signal now lies far above the reference
+        set_signal_and_reference(&mut sensor, Key::Key3, 640, 600);
+        assert!(sensor.recover_if_miscalibrated().unwrap());
+        assert_eq!(
+            sensor.i2c_mut().writes().last(),
+            Some(&(0x38, std::vec![1]))
+        );
+    }
+
+    #[cfg(feature = "reference-data")]
+    #[test]
+    fn recover_if_miscalibrated_waits_while_a_key_is_touched() {
+        let mut sensor = sensor();
+        for key in 0..7 {
+            set_signal_and_reference(&mut sensor, Key::from(key), 600, 600);
+        }
+        set_signal_and_reference(&mut sensor, Key::Key1, 600, 0);
+        sensor.i2c_mut().regs[0x03] = 0b000_0001;
+
+        assert!(!sensor.recover_if_miscalibrated().unwrap());
+        assert!(sensor.i2c_mut().writes().is_empty());
+    }
+
+    #[test]
+    fn config_bytes_round_trip() {
+        let mut sensor = sensor();
+        let mut bytes = [0u8; CONFIG_REGISTER_COUNT];
+        for (offset, byte) in bytes.iter_mut().enumerate() {
+            *byte = 10 + offset as u8;
+        }
+        bytes[21] = 0x23;
+
+        sensor.write_config_bytes(&bytes).unwrap();
+        assert_eq!(sensor.i2c_mut().writes(), [(0x20, bytes.to_vec())]);
+        assert_eq!(sensor.i2c_mut().regs[0x20..0x38], bytes);
+        assert_eq!(sensor.device_reg(&Register::LowPowerMode), 32);
+
+        let mut other = At42qt1070::new(MockI2c::new());
+        other.i2c_mut().regs[0x20..0x38].copy_from_slice(&bytes);
+        assert_eq!(other.read_config_bytes().unwrap(), bytes);
+        assert_eq!(
+            other.i2c_mut().log,
+            [Transaction::Read {
+                start: 0x20,
+                len: CONFIG_REGISTER_COUNT
+            }]
+        );
+    }
+
+    #[test]
+    fn confirmed_setters_write_and_read_back_one_register() {
+        let mut sensor = sensor();
+
+        sensor
+            .set_negative_threshold_confirmed(40, Key::Key2)
+            .unwrap();
+        sensor.set_ave_aks_confirmed(16, 2, Key::Key2).unwrap();
+        sensor
+            .set_detection_integrator_confirmed(6, Key::Key2)
+            .unwrap();
+        sensor
+            .set_fo_mc_guard_confirmed(true, false, Some(Key::Key0))
+            .unwrap();
+        sensor
+            .set_low_power_mode_confirmed(Duration::from_millis(32))
+            .unwrap();
+        sensor
+            .set_max_on_duration_confirmed(Some(Duration::from_millis(1600)))
+            .unwrap();
+
+        let write = |start, value| Transaction::Write {
+            start,
+            values: std::vec![value],
+        };
+        let read = |start| Transaction::Read { start, len: 1 };
+        assert_eq!(
+            sensor.i2c_mut().log,
+            [
+                write(0x22, 40),
+                read(0x22),
+                write(0x29, 0x42),
+                read(0x29),
+                write(0x30, 6),
+                read(0x30),
+                write(0x35, 0x20),
+                read(0x35),
+                write(0x36, 4),
+                read(0x36),
+                write(0x37, 10),
+                read(0x37),
+            ]
+        );
+        assert_eq!(sensor.read_cached_ave_aks(Key::Key2), (16, 2));
+        assert_eq!(sensor.device_reg(&Register::MaxOnDuration), 10);
+    }
+
+    #[test]
+    fn confirmed_setter_reports_a_differing_read_back() {
+        let mut sensor = sensor();
+        // The device ignores the write
+        sensor.i2c_mut().on_read = Some(std::boxed::Box::new(|_, regs| regs[0x36] = 2));
+
+        assert!(matches!(
+            sensor.set_low_power_mode_confirmed(Duration::from_millis(80)),
+            Err(Error::VerifyMismatch {
+                register: Register::LowPowerMode,
+                expected: 10,
+                actual: 2
+            })
+        ));
+        assert_eq!(sensor.device_reg(&Register::LowPowerMode), 2);
+    }
+
+    #[test]
+    fn confirmed_writes_reject_self_clearing_and_invalid_values() {
+        let mut sensor = sensor();
+
+        assert!(matches!(
+            sensor.write_register_confirmed(&Calibrate, 1),
+            Err(Error::InvalidArgument)
+        ));
+        assert!(matches!(
+            sensor.write_register_confirmed(&Reset, 1),
+            Err(Error::InvalidArgument)
+        ));
+        assert!(matches!(
+            sensor.set_ave_aks_confirmed(64, 0, Key::Key1),
+            Err(Error::InvalidArgument)
+        ));
+        assert!(sensor.i2c_mut().log.is_empty());
+    }
+
+    #[test]
+    fn new_with_address_rejects_8_bit_and_out_of_range_addresses() {
+        let new = |address| At42qt1070::new_with_address(MockI2c::new(), address);
+
+        assert!(matches!(new(0x36), Err(Error::EightBitAddress(0x36))));
+        assert!(matches!(new(0x37), Err(Error::EightBitAddress(0x37))));
+        assert!(matches!(new(0x80), Err(Error::InvalidArgument)));
+        assert!(matches!(new(0xFF), Err(Error::InvalidArgument)));
+    }
+
+    #[test]
+    fn new_with_address_talks_to_the_given_address() {
+        let mut i2c = MockI2c::new();
+        i2c.address = 0x2A;
+        let mut sensor = At42qt1070::new_with_address(i2c, 0x2A).unwrap();
+
+        sensor.sync_one(&Register::ChipID).unwrap();
+        assert_eq!(sensor.i2c_mut().nacks, 0);
+    }
+
+    #[test]
+    fn pressed_keys_into_fills_the_pressed_keys_in_order() {
+        let mut sensor = sensor();
+        let mut out = [Key::Key0; 7];
+
+        assert_eq!(sensor.pressed_keys_into(&mut out), 0);
+
+        sensor.i2c_mut().regs[0x03] = 0b100_0110;
+        sensor.read_key_status_byte().unwrap();
+        assert_eq!(sensor.pressed_keys_into(&mut out), 3);
+        assert_eq!(out[..3], [Key::Key1, Key::Key2, Key::Key6]);
+
+        sensor.i2c_mut().regs[0x03] = 0x7F;
+        sensor.read_key_status_byte().unwrap();
+        assert_eq!(sensor.pressed_keys_into(&mut out), 7);
+        assert_eq!(
+            out,
+            [
+                Key::Key0,
+                Key::Key1,
+                Key::Key2,
+                Key::Key3,
+                Key::Key4,
+                Key::Key5,
+                Key::Key6
+            ]
+        );
+    }
+
+    #[test]
+    fn ms_since_last_change_follows_the_polled_timestamps() {
+        let mut sensor = sensor();
+        assert_eq!(sensor.ms_since_last_change(250), 250);
+
+        sensor.i2c_mut().regs[0x03] = 0b000_0001;
+        assert_eq!(sensor.poll_key_events_at(1000).unwrap().count(), 1);
+        assert_eq!(sensor.ms_since_last_change(1300), 300);
+
+        // Polls without a change keep the timestamp
+        assert_eq!(sensor.poll_key_events_at(2000).unwrap().count(), 0);
+        assert_eq!(sensor.ms_since_last_change(2500), 1500);
+
+        sensor.i2c_mut().regs[0x03] = 0;
+        sensor.poll_key_events_at(3000).unwrap();
+        assert_eq!(sensor.ms_since_last_change(3010), 10);
+    }
+
+    #[test]
+    fn ms_since_last_change_survives_clock_wrap_around() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().regs[0x03] = 0b000_0001;
+        sensor.poll_key_events_at(u32::MAX - 9).unwrap();
+
+        assert_eq!(sensor.ms_since_last_change(10), 20);
+    }
+
+    #[cfg(feature = "reference-data")]
+    #[test]
+    fn sync_reference_data_reads_the_reference_block() {
+        let mut sensor = sensor();
+        for key in 0..7u8 {
+            sensor.i2c_mut().set_u16(
+                RegisterMap::reference_ms_addr(Key::from(key)),
+                0x0200 + key as u16,
+            );
+        }
+
+        assert_eq!(
+            sensor.sync_reference_data().unwrap(),
+            [0x0200, 0x0201, 0x0202, 0x0203, 0x0204, 0x0205, 0x0206]
+        );
+        assert_eq!(sensor.read_cached_reference_data(Key::Key6), 0x0206);
+        assert!(sensor.is_valid(&Register::ReferenceDataLs(Key::Key6)));
+        assert_eq!(
+            sensor.i2c_mut().log,
+            [Transaction::Read {
+                start: 0x12,
+                len: 14
+            }]
+        );
+    }
+
+    #[test]
+    fn set_trigger_margin_writes_the_negative_threshold() {
+        let mut sensor = sensor();
+
+        sensor.set_trigger_margin(Key::Key4, 12).unwrap();
+        sensor.set_negative_threshold(13, Key::Key4).unwrap();
+        assert_eq!(
+            sensor.i2c_mut().writes(),
+            [(0x24, std::vec![12]), (0x24, std::vec![13])]
+        );
+        assert_eq!(sensor.read_cached_negative_threshold(Key::Key4), 13);
+    }
+
+    #[test]
+    fn poll_key_events_ordered_reports_keys_of_the_first_read_as_earlier() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().on_read = Some(std::boxed::Box::new(|reads, regs| {
+            regs[0x03] = if reads == 0 { 0b000_0010 } else { 0b001_0010 };
+        }));
+
+        let (events, order) = sensor.poll_key_events_ordered().unwrap();
+        assert_eq!(events.count(), 2);
+        assert_eq!(order.earlier, KeyMask::from(Key::Key1));
+        assert_eq!(order.later, KeyMask::from(Key::Key4));
+        assert_eq!(sensor.i2c_mut().reads(), 2);
+    }
+
+    #[test]
+    fn poll_key_events_ordered_without_a_change_between_the_reads() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().regs[0x03] = 0b000_0110;
+
+        let (_, order) = sensor.poll_key_events_ordered().unwrap();
+        assert_eq!(order.earlier, KeyMask(0b000_0110));
+        assert!(order.later.is_empty());
+    }
+
+    #[test]
+    fn read_registers_coalesces_adjacent_addresses() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().regs[0x20..0x23].copy_from_slice(&[21, 22, 23]);
+        sensor.i2c_mut().regs[0x36] = 9;
+        sensor.i2c_mut().regs[0x03] = 0b000_0100;
+
+        let regs = [
+            NthrKey(Key::Key0),
+            NthrKey(Key::Key1),
+            NthrKey(Key::Key2),
+            Register::LowPowerMode,
+            Register::KeyStatus,
+        ];
+        let mut out = [0u8; 5];
+        sensor.read_registers(&regs, &mut out).unwrap();
+
+        assert_eq!(out, [21, 22, 23, 9, 0b000_0100]);
+        let read = |start, len| Transaction::Read { start, len };
+        assert_eq!(
+            sensor.i2c_mut().log,
+            [read(0x20, 3), read(0x36, 1), read(0x03, 1)]
+        );
+        assert_eq!(sensor.read_cached_negative_threshold(Key::Key2), 23);
+        assert_eq!(sensor.device_reg(&Register::LowPowerMode), 9);
+        assert!(sensor[Key::Key2]);
+    }
+
+    #[test]
+    fn read_registers_reads_scattered_and_descending_addresses_one_by_one() {
+        let mut sensor = sensor();
+        let regs = [DIKey(Key::Key1), NthrKey(Key::Key5), NthrKey(Key::Key4)];
+        let mut out = [0u8; 3];
+
+        sensor.read_registers(&regs, &mut out).unwrap();
+        assert_eq!(out, [4, 20, 20]);
+        let read = |start, len| Transaction::Read { start, len };
+        assert_eq!(
+            sensor.i2c_mut().log,
+            [read(0x2F, 1), read(0x25, 1), read(0x24, 1)]
+        );
+    }
+
+    #[test]
+    fn clear_cached_key_status_invalidates_the_key_status() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().regs[0x03] = 0b000_1001;
+        assert_eq!(sensor.poll_key_events().unwrap().count(), 2);
+        assert!(sensor.is_valid(&Register::KeyStatus));
+
+        sensor.clear_cached_key_status();
+        assert!(!sensor.is_valid(&Register::KeyStatus));
+        assert_eq!(sensor.read_cached_key_status_byte(), 0);
+        assert_eq!(sensor.i2c_mut().reads(), 1);
+
+        let events: std::vec::Vec<_> = sensor.poll_key_events().unwrap().collect();
+        assert_eq!(
+            events,
+            [(Key::Key0, Edge::Pressed), (Key::Key3, Edge::Pressed)]
+        );
+        assert!(sensor.is_valid(&Register::KeyStatus));
+    }
+
+    #[test]
+    fn driver_works_on_a_borrowed_bus() {
+        use embedded_hal::blocking::i2c::Write;
+
+        let mut i2c = MockI2c::new();
+        i2c.regs[0x03] = 0b000_0100;
+        {
+            let mut sensor = At42qt1070::new_borrowed(&mut i2c);
+            assert!(sensor.read_key_status(Key::Key2).unwrap());
+            sensor.set_negative_threshold(30, Key::Key1).unwrap();
+        }
+
+        // The bus is free for other devices again without releasing the driver
+        i2c.write(0x1B, &[0x20, 25]).unwrap();
+        assert_eq!(i2c.regs[0x21], 30);
+        assert_eq!(i2c.regs[0x20], 25);
+
+        let mut sensor = At42qt1070::new(BorrowedI2c(&mut i2c));
+        sensor.sync_one(&NthrKey(Key::Key0)).unwrap();
+        assert_eq!(sensor.read_cached_negative_threshold(Key::Key0), 25);
+    }
+
+    #[test]
+    fn set_aks_group_members_keeps_the_device_ave_without_a_sync() {
+        let mut sensor = sensor();
+        sensor.i2c_mut().regs[0x29] = 16 << 2 | 2;
+
+        let warning = sensor.set_aks_group_members(AksGroup::Group3, &[Key::Key1, Key::Key2]);
+        assert_eq!(warning, Ok(None));
+        assert_eq!(
+            sensor.i2c_mut().log,
+            [
+                Transaction::Read {
+                    start: 0x27,
+                    len: 7
+                },
+                Transaction::Write {
+                    start: 0x27,
+                    values: std::vec![0x04, 0x23, 16 << 2 | 3, 0x21, 0x21, 0x21, 0x21],
+                },
+            ]
+        );
+        assert_eq!(sensor.read_cached_ave_aks(Key::Key2), (16, 3));
+    }
+
+    #[test]
+    fn fo_mc_guard_byte_encodes_no_guard_channel_as_seven() {
+        assert_eq!(fo_mc_guard_byte(false, false, None), 0x07);
+        assert_eq!(
+            fo_mc_guard_byte(true, true, Some(Key::Key2)),
+            0x20 | 0x10 | 0x02
+        );
+        assert_eq!(fo_mc_guard_byte(false, true, Some(Key::Key0)), 0x10);
+    }
+
+    #[test]
+    fn clearing_the_cached_key_status_keeps_has_synced() {
+        let mut sensor = sensor();
+        assert!(!sensor.has_synced());
+
+        sensor.sync_one(&Register::KeyStatus).unwrap();
+        sensor.clear_cached_key_status();
+        assert!(sensor.has_synced());
+        assert!(!sensor.is_valid(&Register::KeyStatus));
     }
 }
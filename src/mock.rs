@@ -0,0 +1,163 @@
+// Simulated device for the unit tests: a register image behind the blocking I2C traits,
+// with a log of every transaction and hooks to inject errors and changing values
+extern crate std;
+
+use crate::register_map::{Register, RegisterMap, REGISTER_COUNT};
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::blocking::i2c;
+use std::boxed::Box;
+use std::vec::Vec;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Nack;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Transaction {
+    Write { start: u8, values: Vec<u8> },
+    Read { start: u8, len: usize },
+}
+
+pub type ReadHook = Box<dyn FnMut(usize, &mut [u8; REGISTER_COUNT as usize])>;
+
+pub struct MockI2c {
+    pub address: u8,
+    pub present: bool,
+    pub regs: [u8; REGISTER_COUNT as usize],
+    pub log: Vec<Transaction>,
+    // Number of upcoming transactions that fail, counted down by every failed attempt
+    pub nack_next: usize,
+    pub nacks: usize,
+    // CHANGE line of the device, released by reading the detection or key status
+    pub change: bool,
+    // Called before every read with the number of reads done so far
+    pub on_read: Option<ReadHook>,
+    reads: usize,
+}
+
+impl MockI2c {
+    // A device at 0x1B holding the datasheet defaults
+    pub fn new() -> Self {
+        let defaults = RegisterMap::datasheet_defaults();
+        let mut regs = [0u8; REGISTER_COUNT as usize];
+        for (addr, value) in regs.iter_mut().enumerate() {
+            if let Some(reg) = Register::from_addr(addr as u8) {
+                *value = defaults.reg_as_byte(&reg);
+            }
+        }
+
+        Self {
+            address: 0x1B,
+            present: true,
+            regs,
+            log: Vec::new(),
+            nack_next: 0,
+            nacks: 0,
+            change: false,
+            on_read: None,
+            reads: 0,
+        }
+    }
+
+    pub fn set_u16(&mut self, ms_addr: u8, value: u16) {
+        self.regs[ms_addr as usize] = (value >> 8) as u8;
+        self.regs[ms_addr as usize + 1] = value as u8;
+    }
+
+    pub fn reads(&self) -> usize {
+        self.log
+            .iter()
+            .filter(|t| matches!(t, Transaction::Read { .. }))
+            .count()
+    }
+
+    pub fn writes(&self) -> Vec<(u8, Vec<u8>)> {
+        self.log
+            .iter()
+            .filter_map(|t| match t {
+                Transaction::Write { start, values } => Some((*start, values.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Addresses written by all logged write transactions, in order
+    pub fn written_addrs(&self) -> Vec<u8> {
+        let mut addrs = Vec::new();
+        for (start, values) in self.writes() {
+            addrs.extend((0..values.len()).map(|offset| start + offset as u8));
+        }
+
+        addrs
+    }
+
+    fn answer(&mut self, address: u8) -> Result<(), Nack> {
+        if !self.present || address != self.address || self.nack_next > 0 {
+            self.nack_next = self.nack_next.saturating_sub(1);
+            self.nacks += 1;
+            return Err(Nack);
+        }
+
+        Ok(())
+    }
+}
+
+impl i2c::Write for MockI2c {
+    type Error = Nack;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Nack> {
+        self.answer(address)?;
+
+        let start = bytes[0];
+        for (offset, value) in bytes[1..].iter().enumerate() {
+            if let Some(reg) = self.regs.get_mut(start as usize + offset) {
+                *reg = *value;
+            }
+        }
+        self.log.push(Transaction::Write {
+            start,
+            values: bytes[1..].to_vec(),
+        });
+
+        Ok(())
+    }
+}
+
+impl i2c::WriteRead for MockI2c {
+    type Error = Nack;
+
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Nack> {
+        self.answer(address)?;
+
+        if let Some(hook) = self.on_read.as_mut() {
+            hook(self.reads, &mut self.regs);
+        }
+        self.reads += 1;
+
+        let start = bytes[0];
+        for (offset, value) in buffer.iter_mut().enumerate() {
+            let addr = start as usize + offset;
+            *value = self.regs.get(addr).copied().unwrap_or_default();
+            if addr == 0x02 || addr == 0x03 {
+                self.change = false;
+            }
+        }
+        self.log.push(Transaction::Read {
+            start,
+            len: buffer.len(),
+        });
+
+        Ok(())
+    }
+}
+
+// Counts the milliseconds waited instead of waiting
+#[derive(Default)]
+pub struct MockDelay {
+    pub ms: u32,
+}
+
+impl DelayMs<u8> for MockDelay {
+    fn delay_ms(&mut self, ms: u8) {
+        self.ms += ms as u32;
+    }
+}
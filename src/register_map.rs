@@ -1,12 +1,21 @@
-use core::borrow::{Borrow, BorrowMut};
+use core::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Deref, DerefMut, Index, IndexMut, Not};
 
 pub const REGISTER_COUNT: u8 = 58;
 
+pub fn u16_from_ms_ls(ms: u8, ls: u8) -> u16 {
+    (ms as u16) << 8 | ls as u16
+}
+
+pub fn ms_ls_from_u16(value: u16) -> (u8, u8) {
+    ((value >> 8) as u8, value as u8)
+}
+
 pub trait RegisterMapRegister {
     fn as_byte(&self) -> u8;
     fn update(&mut self, val: u8);
 }
 
+#[derive(Copy, Clone)]
 pub struct ChipId {
     pub major_id: u8,
     pub minor_id: u8,
@@ -21,6 +30,14 @@ impl Default for ChipId {
     }
 }
 
+impl ChipId {
+    /// A floating bus reads as 0xFF (pull-ups) and a held-down bus as 0x00,
+    /// both mean that no device answered
+    pub fn is_bus_sentinel(val: u8) -> bool {
+        val == 0xFF || val == 0x00
+    }
+}
+
 impl RegisterMapRegister for ChipId {
     fn as_byte(&self) -> u8 {
         self.major_id << 4 | self.minor_id
@@ -32,6 +49,23 @@ impl RegisterMapRegister for ChipId {
     }
 }
 
+/// Decoded firmware version register (0x01), e.g. 0x15 is version 1.5
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FirmwareVersion {
+    pub major: u8,
+    pub minor: u8,
+}
+
+impl FirmwareVersion {
+    pub fn from_byte(val: u8) -> Self {
+        Self {
+            major: val >> 4,
+            minor: val & 0x0F,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
 pub struct DetectionStatus {
     pub calibrate: bool,
     pub overflow: bool,
@@ -72,6 +106,7 @@ impl RegisterMapRegister for DetectionStatus {
     }
 }
 
+#[derive(Copy, Clone)]
 pub struct KeyStatus {
     pub reserved: bool,
     pub key: [bool; 7],
@@ -110,7 +145,7 @@ impl RegisterMapRegister for KeyStatus {
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq)]
 pub struct AveAks {
     pub ave: u8,
     pub aks: u8,
@@ -125,6 +160,50 @@ impl Default for AveAks {
     }
 }
 
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum AksGroup {
+    #[default]
+    None = 0,
+    Group1 = 1,
+    Group2 = 2,
+    Group3 = 3,
+}
+
+impl AksGroup {
+    pub const ALL: [AksGroup; 3] = [AksGroup::Group1, AksGroup::Group2, AksGroup::Group3];
+}
+
+/// Decodes the AKS field of an AVE/AKS register, only the lower two bits are used
+impl From<u8> for AksGroup {
+    fn from(aks: u8) -> Self {
+        match aks & 0x03 {
+            1 => AksGroup::Group1,
+            2 => AksGroup::Group2,
+            3 => AksGroup::Group3,
+            _ => AksGroup::None,
+        }
+    }
+}
+
+impl AveAks {
+    pub fn is_valid_ave(ave: u8) -> bool {
+        matches!(ave, 1 | 2 | 4 | 8 | 16 | 32)
+    }
+
+    pub fn is_valid_aks(aks: u8) -> bool {
+        aks <= 3
+    }
+
+    /// `as_byte` silently drops bits of an AVE above 63 or an AKS above 3, this returns None instead
+    pub fn try_as_byte(&self) -> Option<u8> {
+        if self.ave > 0x3F || self.aks > 0x03 {
+            return None;
+        }
+
+        Some(self.as_byte())
+    }
+}
+
 impl RegisterMapRegister for AveAks {
     fn as_byte(&self) -> u8 {
         self.ave << 2 | self.aks
@@ -136,6 +215,7 @@ impl RegisterMapRegister for AveAks {
     }
 }
 
+#[derive(Copy, Clone, PartialEq)]
 pub struct FastOutDiMaxCalGuardChannel {
     pub fast_out: bool,
     pub max_cal: bool,
@@ -173,6 +253,7 @@ impl RegisterMapRegister for FastOutDiMaxCalGuardChannel {
     }
 }
 
+#[derive(Copy, Clone, PartialEq)]
 pub struct LowPowerMode(u8);
 
 impl Default for LowPowerMode {
@@ -192,13 +273,23 @@ impl RegisterMapRegister for LowPowerMode {
 }
 
 impl LowPowerMode {
+    pub fn is_continuous(&self) -> bool {
+        self.0 == 0
+    }
+
     pub fn from_millis(millis: u16) -> Self {
         Self {
             0: (millis / 8) as u8,
         }
     }
+
+    /// Interval between acquisitions in 8 ms steps, 0 while acquiring continuously
+    pub fn as_millis(&self) -> u16 {
+        self.0 as u16 * 8
+    }
 }
 
+#[derive(Copy, Clone, PartialEq)]
 pub struct MaxOnDuration(u8);
 
 impl Default for MaxOnDuration {
@@ -225,23 +316,73 @@ impl MaxOnDuration {
     }
 }
 
+/// One value per key, indexed by Key. Indexing by usize and the array methods (through Deref)
+/// work as on the plain `[T; 7]` the register map fields used to be.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct PerKey<T>(pub [T; 7]);
+
+impl<T> Index<Key> for PerKey<T> {
+    type Output = T;
+
+    fn index(&self, key: Key) -> &T {
+        &self.0[key.index()]
+    }
+}
+
+impl<T> IndexMut<Key> for PerKey<T> {
+    fn index_mut(&mut self, key: Key) -> &mut T {
+        &mut self.0[key.index()]
+    }
+}
+
+impl<T> Index<usize> for PerKey<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        &self.0[index]
+    }
+}
+
+impl<T> IndexMut<usize> for PerKey<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        &mut self.0[index]
+    }
+}
+
+impl<T> Deref for PerKey<T> {
+    type Target = [T; 7];
+
+    fn deref(&self) -> &[T; 7] {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for PerKey<T> {
+    fn deref_mut(&mut self) -> &mut [T; 7] {
+        &mut self.0
+    }
+}
+
+#[derive(Clone)]
 pub struct RegisterMap {
-    pub chip_id: ChipId,                          //0x00
-    pub firmware_version: u8,                     //0x01
-    pub detection_status: DetectionStatus,        //0x02
-    pub key_status: KeyStatus,                    //0x03
-    pub key_signal_ms: [u8; 7],                   //0x10, 0x0E, 0x0C, 0x0A, 0x08, 0x06, 0x04
-    pub key_signal_ls: [u8; 7],                   //0x11, 0x0F, 0x0D, 0x0B, 0x09, 0x07, 0x05
-    pub reference_data_ms: [u8; 7],               //0x1E, 0x1C, 0x1A, 0x18, 0x16, 0x14, 0x12
-    pub reference_data_ls: [u8; 7],               //0x1F, 0x1D, 0x1B, 0x19, 0x17, 0x15, 0x13
-    pub nthr_key: [u8; 7],                        //0x26 to 0x20
-    pub ave_aks_key: [AveAks; 7],                 //0x2D to 0x27
-    pub di_key: [u8; 7],                          //0x34 to 0x2E
+    pub chip_id: ChipId,                   //0x00
+    pub firmware_version: u8,              //0x01
+    pub detection_status: DetectionStatus, //0x02
+    pub key_status: KeyStatus,             //0x03
+    pub key_signal_ms: PerKey<u8>,         //0x10, 0x0E, 0x0C, 0x0A, 0x08, 0x06, 0x04
+    pub key_signal_ls: PerKey<u8>,         //0x11, 0x0F, 0x0D, 0x0B, 0x09, 0x07, 0x05
+    #[cfg(feature = "reference-data")]
+    pub reference_data_ms: PerKey<u8>, //0x1E, 0x1C, 0x1A, 0x18, 0x16, 0x14, 0x12
+    #[cfg(feature = "reference-data")]
+    pub reference_data_ls: PerKey<u8>, //0x1F, 0x1D, 0x1B, 0x19, 0x17, 0x15, 0x13
+    pub nthr_key: PerKey<u8>,              //0x26 to 0x20
+    pub ave_aks_key: PerKey<AveAks>,       //0x2D to 0x27
+    pub di_key: PerKey<u8>,                //0x34 to 0x2E
     pub fo_mc_guard: FastOutDiMaxCalGuardChannel, //0x35
-    pub low_power_mode: LowPowerMode,             //0x36
-    pub max_on_duration: MaxOnDuration,           //0x37
-    pub calibrate: u8,                            //0x38
-    pub reset: u8,                                //0x39
+    pub low_power_mode: LowPowerMode,      //0x36
+    pub max_on_duration: MaxOnDuration,    //0x37
+    pub calibrate: u8,                     //0x38
+    pub reset: u8,                         //0x39
 }
 
 impl Default for RegisterMap {
@@ -251,12 +392,14 @@ impl Default for RegisterMap {
             firmware_version: 0x15,
             detection_status: DetectionStatus::default(),
             key_status: KeyStatus::default(),
-            key_signal_ms: [0x00; 7],
-            key_signal_ls: [0x00; 7],
-            reference_data_ms: [0x00; 7],
-            reference_data_ls: [0x00; 7],
-            nthr_key: [0x21, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14],
-            ave_aks_key: [
+            key_signal_ms: PerKey([0x00; 7]),
+            key_signal_ls: PerKey([0x00; 7]),
+            #[cfg(feature = "reference-data")]
+            reference_data_ms: PerKey([0x00; 7]),
+            #[cfg(feature = "reference-data")]
+            reference_data_ls: PerKey([0x00; 7]),
+            nthr_key: PerKey([0x21, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14]),
+            ave_aks_key: PerKey([
                 AveAks { ave: 1, aks: 0 },
                 AveAks::default(),
                 AveAks::default(),
@@ -264,8 +407,8 @@ impl Default for RegisterMap {
                 AveAks::default(),
                 AveAks::default(),
                 AveAks::default(),
-            ],
-            di_key: [0x04; 7],
+            ]),
+            di_key: PerKey([0x04; 7]),
             fo_mc_guard: FastOutDiMaxCalGuardChannel::default(),
             low_power_mode: LowPowerMode::default(),
             max_on_duration: MaxOnDuration::default(),
@@ -275,7 +418,7 @@ impl Default for RegisterMap {
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Key {
     Key0 = 0,
     Key1 = 1,
@@ -286,22 +429,145 @@ pub enum Key {
     Key6 = 6,
 }
 
+impl Key {
+    /// Position of the key in per-key arrays
+    ///
+    /// ```
+    /// use at42qt1070::Key;
+    ///
+    /// let signals = [10u16, 11, 12, 13, 14, 15, 16];
+    /// assert_eq!(Key::Key3.index(), 3);
+    /// assert_eq!(signals[Key::Key3.index()], 13);
+    /// ```
+    pub const fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Checked conversion, None for values above 6
+    pub const fn new(val: u8) -> Option<Key> {
+        match val {
+            0 => Some(Key::Key0),
+            1 => Some(Key::Key1),
+            2 => Some(Key::Key2),
+            3 => Some(Key::Key3),
+            4 => Some(Key::Key4),
+            5 => Some(Key::Key5),
+            6 => Some(Key::Key6),
+            _ => None,
+        }
+    }
+
+    /// Accepts b'0' to b'6'
+    pub const fn from_ascii_digit(b: u8) -> Option<Key> {
+        match b {
+            b'0'..=b'6' => Key::new(b - b'0'),
+            _ => None,
+        }
+    }
+}
+
+/// Wraps values above 6 around (modulo 7), use Key::new to reject them instead
 impl From<u8> for Key {
     fn from(val: u8) -> Self {
-        let val = val % 7;
-        match val {
+        match val % 7 {
             0 => Key::Key0,
             1 => Key::Key1,
             2 => Key::Key2,
             3 => Key::Key3,
             4 => Key::Key4,
             5 => Key::Key5,
-            6 => Key::Key6,
-            _ => panic!(),
+            _ => Key::Key6,
         }
     }
 }
 
+/// Same bit layout as the key status register, bit 0 is Key0
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyMask(pub u8);
+
+/// Set of keys, e.g. the members of an AKS group
+pub type KeySet = KeyMask;
+
+impl KeyMask {
+    pub fn contains(&self, key: Key) -> bool {
+        self.0 & 1 << key as u8 != 0
+    }
+
+    pub fn insert(&mut self, key: Key) {
+        self.0 |= 1 << key as u8;
+    }
+
+    pub fn remove(&mut self, key: Key) {
+        self.0 &= !(1 << key as u8);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    /// Keys in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = Key> {
+        let mask = *self;
+        (0..7u8)
+            .map(Key::from)
+            .filter(move |key| mask.contains(*key))
+    }
+}
+
+impl BitOr for KeyMask {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for KeyMask {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl BitAnd for KeyMask {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl BitAndAssign for KeyMask {
+    fn bitand_assign(&mut self, rhs: Self) {
+        self.0 &= rhs.0;
+    }
+}
+
+/// Complement within the seven keys
+impl Not for KeyMask {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        Self(!self.0 & 0x7F)
+    }
+}
+
+impl From<Key> for KeyMask {
+    fn from(key: Key) -> Self {
+        Self(1 << key as u8)
+    }
+}
+
+impl From<&KeyStatus> for KeyMask {
+    fn from(status: &KeyStatus) -> Self {
+        Self(status.as_byte() & 0x7F)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Register {
     ChipID,
     FirmwareVersion,
@@ -321,7 +587,100 @@ pub enum Register {
     Reset,
 }
 
+impl Register {
+    /// Usable in const and static tables,
+    /// e.g. `const NTHR: u8 = Register::NthrKey(Key::Key2).addr();`
+    pub const fn addr(self) -> u8 {
+        RegisterMap::get_register_addr(&self)
+    }
+
+    /// Inverse of RegisterMap::get_register_addr
+    pub fn from_addr(addr: u8) -> Option<Register> {
+        let key = |base: u8, step: u8| Key::from((addr - base) / step);
+        Some(match addr {
+            0x00 => Register::ChipID,
+            0x01 => Register::FirmwareVersion,
+            0x02 => Register::DetectionStatus,
+            0x03 => Register::KeyStatus,
+            0x04..=0x11 if addr & 1 == 0 => Register::KeySignalMs(key(0x04, 2)),
+            0x04..=0x11 => Register::KeySignalLs(key(0x05, 2)),
+            0x12..=0x1F if addr & 1 == 0 => Register::ReferenceDataMs(key(0x12, 2)),
+            0x12..=0x1F => Register::ReferenceDataLs(key(0x13, 2)),
+            0x20..=0x26 => Register::NthrKey(key(0x20, 1)),
+            0x27..=0x2D => Register::AveAksKey(key(0x27, 1)),
+            0x2E..=0x34 => Register::DIKey(key(0x2E, 1)),
+            0x35 => Register::FoMcGuard,
+            0x36 => Register::LowPowerMode,
+            0x37 => Register::MaxOnDuration,
+            0x38 => Register::Calibrate,
+            0x39 => Register::Reset,
+            _ => return None,
+        })
+    }
+
+    /// Chip ID, firmware version, status, signal and reference registers
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Register::ChipID
+                | Register::FirmwareVersion
+                | Register::DetectionStatus
+                | Register::KeyStatus
+                | Register::KeySignalMs(_)
+                | Register::KeySignalLs(_)
+                | Register::ReferenceDataMs(_)
+                | Register::ReferenceDataLs(_)
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl Register {
+    /// Per-key registers are addressed by appending the key index, e.g. "nthr3" or "signal0"
+    pub fn from_name(name: &str) -> Option<Register> {
+        match name {
+            "chip_id" => return Some(Register::ChipID),
+            "firmware_version" => return Some(Register::FirmwareVersion),
+            "detection_status" => return Some(Register::DetectionStatus),
+            "key_status" => return Some(Register::KeyStatus),
+            "fo_mc_guard" => return Some(Register::FoMcGuard),
+            "low_power_mode" => return Some(Register::LowPowerMode),
+            "max_on_duration" => return Some(Register::MaxOnDuration),
+            "calibrate" => return Some(Register::Calibrate),
+            "reset" => return Some(Register::Reset),
+            _ => {}
+        }
+
+        let key = Key::from_ascii_digit(*name.as_bytes().last()?)?;
+
+        match &name[..name.len() - 1] {
+            "signal" | "signal_ms" => Some(Register::KeySignalMs(key)),
+            "signal_ls" => Some(Register::KeySignalLs(key)),
+            "reference" | "reference_ms" => Some(Register::ReferenceDataMs(key)),
+            "reference_ls" => Some(Register::ReferenceDataLs(key)),
+            "nthr" => Some(Register::NthrKey(key)),
+            "ave_aks" => Some(Register::AveAksKey(key)),
+            "di" => Some(Register::DIKey(key)),
+            _ => None,
+        }
+    }
+}
+
 impl RegisterMap {
+    /// Register values after power-up or reset (chapter 5):
+    /// Chip ID 0x2E, firmware version 0x15, NTHR 33 for Key0 and 20 for the others,
+    /// AVE 1 / AKS 0 for Key0 (the guard channel) and AVE 8 / AKS 1 for the others, DI 4,
+    /// FastOut and MaxCal off with Key0 as guard channel, LPM 2 (16 ms),
+    /// max on duration 180 (28.8 s). Status, signal and reference registers read 0.
+    pub fn datasheet_defaults() -> RegisterMap {
+        RegisterMap::default()
+    }
+
+    /// Value of a single register after power-up or reset
+    pub fn reset_value(reg: &Register) -> u8 {
+        RegisterMap::datasheet_defaults().reg_as_byte(reg)
+    }
+
     pub fn reg_as_byte(&self, reg: &Register) -> u8 {
         match reg {
             Register::ChipID => self.chip_id.as_byte(),
@@ -330,8 +689,12 @@ impl RegisterMap {
             Register::KeyStatus => self.key_status.as_byte(),
             Register::KeySignalMs(key) => *self.get_key_signal_register(key, true),
             Register::KeySignalLs(key) => *self.get_key_signal_register(key, false),
+            #[cfg(feature = "reference-data")]
             Register::ReferenceDataMs(key) => *self.get_reference_data_register(key, true),
+            #[cfg(feature = "reference-data")]
             Register::ReferenceDataLs(key) => *self.get_reference_data_register(key, false),
+            #[cfg(not(feature = "reference-data"))]
+            Register::ReferenceDataMs(_) | Register::ReferenceDataLs(_) => 0,
             Register::NthrKey(key) => *self.get_nthr_key_register(key),
             Register::AveAksKey(key) => self.get_ave_aks_key_register(&key).as_byte(),
             Register::DIKey(key) => *self.get_di_key_register(key),
@@ -343,63 +706,131 @@ impl RegisterMap {
         }
     }
 
+    /// Updates the cached value of a writable register, read-only registers are left untouched
+    pub fn set_reg(&mut self, reg: &Register, value: u8) {
+        match reg {
+            Register::NthrKey(key) => self.nthr_key[*key] = value,
+            Register::AveAksKey(key) => self.ave_aks_key[*key].update(value),
+            Register::DIKey(key) => self.di_key[*key] = value,
+            Register::FoMcGuard => self.fo_mc_guard.update(value),
+            Register::LowPowerMode => self.low_power_mode.update(value),
+            Register::MaxOnDuration => self.max_on_duration.update(value),
+            Register::Calibrate => self.calibrate = value,
+            Register::Reset => self.reset = value,
+            _ => {}
+        }
+    }
+
+    /// Updates the cached value of any register, as read from the device
+    pub fn update_reg(&mut self, reg: &Register, value: u8) {
+        match reg {
+            Register::ChipID => self.chip_id.update(value),
+            Register::FirmwareVersion => self.firmware_version = value,
+            Register::DetectionStatus => self.detection_status.update(value),
+            Register::KeyStatus => self.key_status.update(value),
+            Register::KeySignalMs(key) => self.key_signal_ms[*key] = value,
+            Register::KeySignalLs(key) => self.key_signal_ls[*key] = value,
+            #[cfg(feature = "reference-data")]
+            Register::ReferenceDataMs(key) => self.reference_data_ms[*key] = value,
+            #[cfg(feature = "reference-data")]
+            Register::ReferenceDataLs(key) => self.reference_data_ls[*key] = value,
+            _ => self.set_reg(reg, value),
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8; REGISTER_COUNT as usize]) -> RegisterMap {
+        let byte = |reg: Register| bytes[RegisterMap::get_register_addr(&reg) as usize];
+        let mut map = RegisterMap::default();
+
+        map.chip_id.update(byte(Register::ChipID));
+        map.firmware_version = byte(Register::FirmwareVersion);
+        map.detection_status.update(byte(Register::DetectionStatus));
+        map.key_status.update(byte(Register::KeyStatus));
+        for key in 0..7 {
+            let key = Key::from(key);
+            map.key_signal_ms[key] = byte(Register::KeySignalMs(key));
+            map.key_signal_ls[key] = byte(Register::KeySignalLs(key));
+            #[cfg(feature = "reference-data")]
+            {
+                map.reference_data_ms[key] = byte(Register::ReferenceDataMs(key));
+                map.reference_data_ls[key] = byte(Register::ReferenceDataLs(key));
+            }
+            map.nthr_key[key] = byte(Register::NthrKey(key));
+            map.ave_aks_key[key].update(byte(Register::AveAksKey(key)));
+            map.di_key[key] = byte(Register::DIKey(key));
+        }
+        map.fo_mc_guard.update(byte(Register::FoMcGuard));
+        map.low_power_mode.update(byte(Register::LowPowerMode));
+        map.max_on_duration.update(byte(Register::MaxOnDuration));
+        map.calibrate = byte(Register::Calibrate);
+        map.reset = byte(Register::Reset);
+
+        map
+    }
+
     pub fn get_key_signal_register_mut(&mut self, key: &Key, ms: bool) -> &mut u8 {
         if ms {
-            self.key_signal_ms[*key as usize].borrow_mut()
+            &mut self.key_signal_ms[*key]
         } else {
-            self.key_signal_ls[*key as usize].borrow_mut()
+            &mut self.key_signal_ls[*key]
         }
     }
 
     pub fn get_key_signal_register(&self, key: &Key, ms: bool) -> &u8 {
         if ms {
-            self.key_signal_ms[*key as usize].borrow()
+            &self.key_signal_ms[*key]
         } else {
-            self.key_signal_ls[*key as usize].borrow()
+            &self.key_signal_ls[*key]
         }
     }
 
+    #[cfg(feature = "reference-data")]
     pub fn get_reference_data_register_mut(&mut self, key: &Key, ms: bool) -> &mut u8 {
         if ms {
-            self.reference_data_ms[*key as usize].borrow_mut()
+            &mut self.reference_data_ms[*key]
         } else {
-            self.reference_data_ls[*key as usize].borrow_mut()
+            &mut self.reference_data_ls[*key]
         }
     }
 
+    #[cfg(feature = "reference-data")]
     pub fn get_reference_data_register(&self, key: &Key, ms: bool) -> &u8 {
         if ms {
-            self.reference_data_ms[*key as usize].borrow()
+            &self.reference_data_ms[*key]
         } else {
-            self.reference_data_ls[*key as usize].borrow()
+            &self.reference_data_ls[*key]
         }
     }
 
     pub fn get_nthr_key_register_mut(&mut self, key: &Key) -> &mut u8 {
-        self.nthr_key[*key as usize].borrow_mut()
+        &mut self.nthr_key[*key]
     }
 
     pub fn get_nthr_key_register(&self, key: &Key) -> &u8 {
-        self.nthr_key[*key as usize].borrow()
+        &self.nthr_key[*key]
     }
 
     pub fn get_ave_aks_key_register_mut(&mut self, key: &Key) -> &mut AveAks {
-        self.ave_aks_key[*key as usize].borrow_mut()
+        &mut self.ave_aks_key[*key]
     }
 
     pub fn get_ave_aks_key_register(&self, key: &Key) -> &AveAks {
-        self.ave_aks_key[*key as usize].borrow()
+        &self.ave_aks_key[*key]
     }
 
     pub fn get_di_key_register_mut(&mut self, key: &Key) -> &mut u8 {
-        self.di_key[*key as usize].borrow_mut()
+        &mut self.di_key[*key]
     }
 
     pub fn get_di_key_register(&self, key: &Key) -> &u8 {
-        self.di_key[*key as usize].borrow()
+        &self.di_key[*key]
     }
 
-    pub fn get_register_addr(reg: &Register) -> u8 {
+    /// Being a const fn, the address of a register known at compile time folds into a
+    /// constant (see `Register::addr`). With a register only known at runtime, e.g. in
+    /// `sync_one`, the match is still evaluated on every call, so this is not a measured
+    /// speedup for that path.
+    pub const fn get_register_addr(reg: &Register) -> u8 {
         match reg {
             Register::ChipID => 0x00,
             Register::FirmwareVersion => 0x01,
@@ -419,4 +850,251 @@ impl RegisterMap {
             Register::Reset => 0x39,
         }
     }
+
+    pub const fn signal_ms_addr(key: Key) -> u8 {
+        Self::get_register_addr(&Register::KeySignalMs(key))
+    }
+
+    pub const fn signal_ls_addr(key: Key) -> u8 {
+        Self::get_register_addr(&Register::KeySignalLs(key))
+    }
+
+    pub const fn reference_ms_addr(key: Key) -> u8 {
+        Self::get_register_addr(&Register::ReferenceDataMs(key))
+    }
+
+    pub const fn reference_ls_addr(key: Key) -> u8 {
+        Self::get_register_addr(&Register::ReferenceDataLs(key))
+    }
+
+    pub const fn nthr_addr(key: Key) -> u8 {
+        Self::get_register_addr(&Register::NthrKey(key))
+    }
+
+    pub const fn ave_aks_addr(key: Key) -> u8 {
+        Self::get_register_addr(&Register::AveAksKey(key))
+    }
+
+    pub const fn di_addr(key: Key) -> u8 {
+        Self::get_register_addr(&Register::DIKey(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn from_name_parses_per_key_registers() {
+        assert_eq!(
+            Register::from_name("nthr3"),
+            Some(Register::NthrKey(Key::Key3))
+        );
+        assert_eq!(
+            Register::from_name("signal0"),
+            Some(Register::KeySignalMs(Key::Key0))
+        );
+        assert_eq!(
+            Register::from_name("low_power_mode"),
+            Some(Register::LowPowerMode)
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn from_name_rejects_invalid_names() {
+        assert_eq!(Register::from_name("nthr7"), None);
+        assert_eq!(Register::from_name(""), None);
+        assert_eq!(Register::from_name("0"), None);
+        assert_eq!(Register::from_name("nthr"), None);
+    }
+
+    #[test]
+    fn u16_round_trips_through_ms_ls() {
+        assert_eq!(u16_from_ms_ls(0x12, 0x34), 0x1234);
+        assert_eq!(ms_ls_from_u16(0x1234), (0x12, 0x34));
+        for value in [0, 1, 0x00FF, 0x0100, 0x7FFF, 0xFFFF].iter() {
+            let (ms, ls) = ms_ls_from_u16(*value);
+            assert_eq!(u16_from_ms_ls(ms, ls), *value);
+        }
+    }
+
+    #[test]
+    fn get_register_addr_is_usable_in_const_context() {
+        const NTHR2: u8 = RegisterMap::get_register_addr(&Register::NthrKey(Key::Key2));
+        const RESET: u8 = RegisterMap::get_register_addr(&Register::Reset);
+
+        assert_eq!(NTHR2, 0x22);
+        assert_eq!(RESET, 0x39);
+    }
+
+    #[test]
+    fn per_key_keeps_the_array_api() {
+        let mut map = RegisterMap::default();
+        assert_eq!(map.nthr_key[0], 0x21);
+        assert_eq!(map.nthr_key[Key::Key0], 0x21);
+
+        map.nthr_key[1] = 5;
+        assert_eq!(map.nthr_key[Key::Key1], 5);
+        assert_eq!(map.di_key.iter().filter(|di| **di == 4).count(), 7);
+        map.di_key.iter_mut().for_each(|di| *di = 2);
+        assert_eq!(*map.di_key, [2; 7]);
+        assert_eq!(map.ave_aks_key.len(), 7);
+    }
+
+    #[test]
+    fn address_helpers_match_get_register_addr() {
+        for key in 0..7u8 {
+            let key = Key::from(key);
+            let addr = |reg: Register| RegisterMap::get_register_addr(&reg);
+            assert_eq!(
+                RegisterMap::signal_ms_addr(key),
+                addr(Register::KeySignalMs(key))
+            );
+            assert_eq!(
+                RegisterMap::signal_ls_addr(key),
+                addr(Register::KeySignalLs(key))
+            );
+            assert_eq!(
+                RegisterMap::reference_ms_addr(key),
+                addr(Register::ReferenceDataMs(key))
+            );
+            assert_eq!(
+                RegisterMap::reference_ls_addr(key),
+                addr(Register::ReferenceDataLs(key))
+            );
+            assert_eq!(RegisterMap::nthr_addr(key), addr(Register::NthrKey(key)));
+            assert_eq!(
+                RegisterMap::ave_aks_addr(key),
+                addr(Register::AveAksKey(key))
+            );
+            assert_eq!(RegisterMap::di_addr(key), addr(Register::DIKey(key)));
+        }
+        assert_eq!(RegisterMap::signal_ms_addr(Key::Key0), 0x04);
+        assert_eq!(RegisterMap::reference_ls_addr(Key::Key6), 0x1F);
+        assert_eq!(RegisterMap::di_addr(Key::Key6), 0x34);
+    }
+
+    #[test]
+    fn key_set_operations() {
+        let mut set = KeySet::default();
+        assert!(set.is_empty());
+        set.insert(Key::Key1);
+        set.insert(Key::Key5);
+        set.insert(Key::Key5);
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(Key::Key5) && !set.contains(Key::Key0));
+
+        let other = KeySet::from(Key::Key5) | KeySet::from(Key::Key6);
+        assert_eq!(set | other, KeyMask(0b110_0010));
+        assert_eq!(set & other, KeySet::from(Key::Key5));
+
+        let mut combined = set;
+        combined |= other;
+        combined &= !KeySet::from(Key::Key1);
+        assert_eq!(
+            combined.iter().collect::<std::vec::Vec<_>>(),
+            [Key::Key5, Key::Key6]
+        );
+
+        set.remove(Key::Key1);
+        assert_eq!(set, KeySet::from(Key::Key5));
+    }
+
+    #[test]
+    fn key_set_complement_stays_within_seven_keys() {
+        assert_eq!(!KeySet::default(), KeyMask(0x7F));
+        assert_eq!(!KeyMask(0x7F), KeySet::default());
+        assert_eq!(!KeySet::from(Key::Key0), KeyMask(0x7E));
+        assert_eq!((!KeySet::default()).len(), 7);
+    }
+
+    #[test]
+    fn firmware_version_splits_the_nibbles() {
+        assert_eq!(
+            FirmwareVersion::from_byte(0x15),
+            FirmwareVersion { major: 1, minor: 5 }
+        );
+        assert_eq!(
+            FirmwareVersion::from_byte(0xA0),
+            FirmwareVersion {
+                major: 10,
+                minor: 0
+            }
+        );
+    }
+
+    #[test]
+    fn datasheet_defaults_match_the_documented_values() {
+        let reset_value = RegisterMap::reset_value;
+
+        assert_eq!(reset_value(&Register::ChipID), 0x2E);
+        assert_eq!(reset_value(&Register::FirmwareVersion), 0x15);
+        assert_eq!(reset_value(&Register::NthrKey(Key::Key0)), 33);
+        assert_eq!(reset_value(&Register::AveAksKey(Key::Key0)), 0x04);
+        for key in 1..7 {
+            assert_eq!(reset_value(&Register::NthrKey(Key::from(key))), 20);
+            assert_eq!(reset_value(&Register::AveAksKey(Key::from(key))), 0x21);
+        }
+        for key in 0..7 {
+            assert_eq!(reset_value(&Register::DIKey(Key::from(key))), 4);
+            assert_eq!(reset_value(&Register::KeySignalMs(Key::from(key))), 0);
+            assert_eq!(reset_value(&Register::ReferenceDataLs(Key::from(key))), 0);
+        }
+        assert_eq!(reset_value(&Register::FoMcGuard), 0x00);
+        assert_eq!(reset_value(&Register::LowPowerMode), 2);
+        assert_eq!(reset_value(&Register::MaxOnDuration), 180);
+        assert_eq!(reset_value(&Register::DetectionStatus), 0);
+        assert_eq!(reset_value(&Register::KeyStatus), 0);
+    }
+
+    #[test]
+    fn key_from_ascii_digit() {
+        assert_eq!(Key::from_ascii_digit(b'0'), Some(Key::Key0));
+        assert_eq!(Key::from_ascii_digit(b'3'), Some(Key::Key3));
+        assert_eq!(Key::from_ascii_digit(b'6'), Some(Key::Key6));
+        assert_eq!(Key::from_ascii_digit(b'7'), None);
+        assert_eq!(Key::from_ascii_digit(b'/'), None);
+        assert_eq!(Key::from_ascii_digit(3), None);
+    }
+
+    #[test]
+    fn ave_aks_try_as_byte_rejects_overflowing_fields() {
+        assert_eq!(AveAks { ave: 8, aks: 1 }.try_as_byte(), Some(0x21));
+        assert_eq!(AveAks { ave: 63, aks: 3 }.try_as_byte(), Some(0xFF));
+        assert_eq!(AveAks { ave: 64, aks: 0 }.try_as_byte(), None);
+        assert_eq!(AveAks { ave: 8, aks: 4 }.try_as_byte(), None);
+    }
+
+    #[test]
+    fn register_addr_in_a_const_table() {
+        const CONFIG_ADDRS: [u8; 3] = [
+            Register::NthrKey(Key::Key2).addr(),
+            Register::FoMcGuard.addr(),
+            Register::Calibrate.addr(),
+        ];
+        static STATUS_ADDR: u8 = Register::KeyStatus.addr();
+
+        assert_eq!(CONFIG_ADDRS, [0x22, 0x35, 0x38]);
+        assert_eq!(STATUS_ADDR, 0x03);
+        for addr in 0..REGISTER_COUNT {
+            if let Some(reg) = Register::from_addr(addr) {
+                assert_eq!(reg.addr(), addr);
+            }
+        }
+    }
+
+    #[test]
+    fn key_new_rejects_what_from_wraps() {
+        for val in 0..7u8 {
+            assert_eq!(Key::new(val), Some(Key::from(val)));
+        }
+        assert_eq!(Key::new(7), None);
+        assert_eq!(Key::new(0xFF), None);
+        assert_eq!(Key::from(7), Key::Key0);
+        assert_eq!(Key::from(0xFF), Key::Key3);
+    }
 }